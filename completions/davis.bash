@@ -56,7 +56,7 @@ _davis ()
 		word="${COMP_WORDS[c]}"
 		case "$word" in
 			--host=*) MPD_HOST="${word#--host=}" ;;
-			--plain|-p|-v|--verbose) ;;
+			--plain|-p|-v|--verbose|--json) ;;
 			*) command="$word"; break ;;
 		esac
 		c=$((c+1))
@@ -77,20 +77,39 @@ _davis ()
 	# Complete command arguments
 	case "$command" in
 	add)         _davis_add ;;
+	autodj)      COMPREPLY=($(compgen -W "--upcoming --history --albums --remove --keep" -- "$cur")) ;;
+	album-info)  _davis_add ;;
+	bar)         COMPREPLY=($(compgen -W "--style waybar i3bar polybar tmux" -- "$cur")) ;;
+	browse)      COMPREPLY=($(compgen -W "--tags --dirs" -- "$cur")) ;;
 	clear)       ;; # no arguments
 	current)     ;; # no arguments
 	del)         ;; # don't complete numbers
+	hosts)       ;; # no arguments
+	info)        _davis_add ;;
 	load)        ;;
-	ls)          _davis_add ;;
+	ls)
+		case "${COMP_WORDS[COMP_CWORD-1]}:$cur" in
+			--sort:*) COMPREPLY=($(compgen -W "name mtime duration" -- "$cur")) ;;
+			*:-*) COMPREPLY=($(compgen -W "-R -l -F -r -0 --sort --fields" -- "$cur")) ;;
+			*) _davis_add ;;
+		esac ;;
 	mv)          ;; # don't complete numbers
 	next)        ;; # no arguments
 	pause)       ;; # no arguments
 	play)        ;; # don't complete numbers
 	prev)        ;; # no arguments
+	random-album) COMPREPLY=($(compgen -W "--play --print --format" -- "$cur")) ;;
+	random-track) COMPREPLY=($(compgen -W "--play --print --format" -- "$cur")) ;;
+	rate)        ;; # don't complete numbers
+	related)     COMPREPLY=($(compgen -W "--add" -- "$cur")) ;;
 	seek)        ;; # don't complete numbers
+	smart)       COMPREPLY=($(compgen -W "list show load save" -- "$cur")) ;;
 	status)      ;; # no arguments
+	sticker)     COMPREPLY=($(compgen -W "list get set delete find count-plays" -- "$cur")) ;;
 	stop)        ;; # no arguments
 	toggle)      ;; # no arguments
+	album|work)  COMPREPLY=($(compgen -W "--add --replace --play-from-here" -- "$cur")) ;;
+	rescan)      _davis_add ;;
 	update)      _davis_add ;;
 	*)           ;;
 	esac
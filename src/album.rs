@@ -0,0 +1,241 @@
+use crate::ansi::{theme, FormattedString, Style};
+use crate::error::Error;
+use crate::json;
+use crate::raw;
+use crate::sort;
+use crate::status::duration_format;
+use crate::table::{Align, Row, Table};
+use crate::tags::Tags;
+use mpdrs::{Song, State};
+
+// A group of songs which the queue shows under a common header.
+#[derive(Clone, Copy)]
+pub enum Group {
+    Album,
+    Work,
+}
+
+impl Group {
+    // The tags identifying the group of a song, in order of preference.
+    fn keys(self) -> &'static [[&'static str; 2]] {
+        match self {
+            Group::Work => &[["work", "composer"]],
+            Group::Album => &[["album", "albumartist"], ["album", "artist"]],
+        }
+    }
+
+    // The first tags of the group which are all set for the song.
+    pub fn tags(self, tags: &Tags) -> Option<[&'static str; 2]> {
+        self.keys()
+            .iter()
+            .copied()
+            .find(|keys| keys.iter().all(|k| !tags.get(k).is_empty()))
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Group::Album => "album",
+            Group::Work => "work",
+        }
+    }
+}
+
+pub enum Mode {
+    List,
+    // Add the songs to the end of the queue.
+    Add,
+    // Replace the queue with the songs, without interrupting the current song.
+    Replace,
+    // Insert the songs following the current song after it in the queue.
+    PlayFromHere,
+}
+
+pub fn album(
+    client: &mut mpdrs::Client,
+    raw: &mut raw::Connection,
+    group: Group,
+    mode: Mode,
+    json: bool,
+) -> Result<(), Error> {
+    let Some(current) = client.currentsong()? else {
+        println!("Not playing.");
+        return Ok(());
+    };
+    let tags = Tags::from_song(&current);
+    let Some(keys) = group.tags(&tags) else {
+        println!("The current song is not part of a {}.", group.name());
+        return Ok(());
+    };
+
+    let songs = songs(raw, group, &tags, keys)?;
+    let queue = client.queue()?;
+    let position = current.place.map_or(0, |p| p.pos as usize);
+    let index = songs.iter().position(|s| s.file == current.file);
+    let status = client.status()?;
+    let commands: Vec<(&str, Vec<String>)> = match (mode, index) {
+        (Mode::List, _) => {
+            // Songs in the queue are shown with their position in it.
+            let songs = songs
+                .into_iter()
+                .map(|song| {
+                    let queued = match song.file == current.file {
+                        true => Some(&current),
+                        false => queue.iter().find(|q| q.file == song.file),
+                    };
+                    queued.cloned().unwrap_or(song)
+                })
+                .collect::<Vec<_>>();
+            if json {
+                let songs = songs.iter().map(json::song).collect();
+                println!("{}", json::Value::Array(songs));
+            } else {
+                let header = tags.joined(&keys, " - ").unwrap_or_default();
+                print!("{}", render(&header, &songs, &current, group));
+            }
+            return Ok(());
+        }
+        (Mode::Add, _) => songs
+            .iter()
+            .map(|song| ("add", vec![song.file.clone()]))
+            .collect(),
+        (Mode::Replace, Some(index)) => {
+            let mut commands = vec![];
+            if position + 1 < queue.len() {
+                let range = format!("{}:{}", position + 1, queue.len());
+                commands.push(("delete", vec![range]));
+            }
+            if position > 0 {
+                commands.push(("delete", vec![format!("0:{}", position)]));
+            }
+            for (i, song) in songs[..index].iter().enumerate() {
+                commands.push(("add", vec![song.file.clone(), i.to_string()]));
+            }
+            for song in &songs[index + 1..] {
+                commands.push(("add", vec![song.file.clone()]));
+            }
+            commands
+        }
+        (Mode::PlayFromHere, Some(index)) => {
+            let mut commands = songs[index + 1..]
+                .iter()
+                .enumerate()
+                .map(|(i, song)| {
+                    (
+                        "add",
+                        vec![song.file.clone(), (position + 1 + i).to_string()],
+                    )
+                })
+                .collect::<Vec<_>>();
+            if status.state == State::Stop {
+                commands.push(("play", vec![position.to_string()]));
+            }
+            commands
+        }
+        // The current song is not in the database, such as a stream.
+        (_, None) => {
+            println!("The current song is not in the database.");
+            return Ok(());
+        }
+    };
+    let commands = commands
+        .iter()
+        .map(|(command, args)| (*command, args.iter().map(String::as_str).collect()))
+        .collect::<Vec<_>>();
+    if !commands.is_empty() {
+        raw.command_list(&commands)?;
+    }
+    Ok(())
+}
+
+// The songs of the group identified by keys, such as from Group::tags, in
+// order.
+pub fn songs(
+    raw: &mut raw::Connection,
+    group: Group,
+    tags: &Tags,
+    keys: [&str; 2],
+) -> Result<Vec<Song>, Error> {
+    // A work is limited to the album of the song, as there may be several
+    // recordings of it.
+    let mut args = vec![];
+    let filter_keys = match group {
+        Group::Work => [&keys[..], &["album"]].concat(),
+        Group::Album => keys.to_vec(),
+    };
+    for key in filter_keys {
+        for value in tags.get(key) {
+            args.extend([key, value]);
+        }
+    }
+    let mut songs = raw.songs("find", &args)?;
+    sort::natural(&mut songs);
+    if let Group::Work = group {
+        sort::movements(&mut songs);
+    }
+    Ok(songs)
+}
+
+// The songs under a header, with their queue position if queued, their disc
+// and track or movement number, title and duration.
+fn render(header: &str, songs: &[Song], current: &Song, group: Group) -> String {
+    let discs = songs
+        .iter()
+        .filter_map(|s| Tags::from_song(s).get_option_joined("disc"))
+        .filter_map(|d| sort::number(&d))
+        .max()
+        .unwrap_or(1);
+    let fields = songs
+        .iter()
+        .map(|song| {
+            let tags = Tags::from_song(song);
+            let number = |tag| tags.get_option_joined(tag).and_then(|n| sort::number(&n));
+            let position = song.place.map(|p| (p.pos + 1).to_string());
+            let (number, title) = match (group, tags.get_option_joined("movement")) {
+                (Group::Work, Some(movement)) => (
+                    number("movementnumber").map(|n| n.to_string()),
+                    Some(movement),
+                ),
+                _ => {
+                    let track = number("track").map(|track| match number("disc") {
+                        Some(disc) if discs > 1 => format!("{}-{:02}", disc, track),
+                        _ => format!("{:02}", track),
+                    });
+                    (track, song.title.clone())
+                }
+            };
+            (
+                song.file == current.file,
+                position.unwrap_or_default(),
+                number.unwrap_or_default(),
+                song.duration
+                    .map(|d| duration_format(&d))
+                    .unwrap_or_default(),
+                title.unwrap_or_else(|| song.file.clone()),
+            )
+        })
+        .collect::<Vec<_>>();
+    let rows = fields
+        .iter()
+        .map(|(is_current, position, number, duration, title)| {
+            let style = if *is_current {
+                theme().current
+            } else {
+                Style::default()
+            };
+            Row::new(vec![
+                FormattedString::new(position).style(style),
+                FormattedString::new(number).style(style),
+                FormattedString::new(duration).style(style),
+                FormattedString::new(title).style(style),
+            ])
+        })
+        .collect::<Vec<_>>();
+    format!(
+        "{}\n{}\n",
+        FormattedString::new(header).style(theme().header),
+        Table::new(&rows)
+            .align(0, Align::Right)
+            .align(1, Align::Right)
+            .align(2, Align::Right)
+    )
+}
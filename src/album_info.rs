@@ -0,0 +1,152 @@
+use crate::album::{self, Group};
+use crate::ansi::{theme, FormattedString};
+use crate::config::{Config, Tag};
+use crate::error::Error;
+use crate::json;
+use crate::raw;
+use crate::sort;
+use crate::status::duration_format;
+use crate::table::{Row, Table};
+use crate::tags::Tags;
+use mpdrs::error::Error as MpdError;
+use std::collections::HashSet;
+use std::time::Duration;
+
+// Summarize the album of the song at path, or of the current song: the
+// configured tags of all of its songs, the number of discs and tracks, the
+// total duration, the audio formats and whether there is cover art.
+pub fn album_info(
+    client: &mut mpdrs::Client,
+    raw: &mut raw::Connection,
+    conf: &Config,
+    path: Option<&str>,
+    json: bool,
+) -> Result<(), Error> {
+    let song = match path {
+        Some(path) => raw.songs("find", &["file", path])?.into_iter().next(),
+        None => client.currentsong()?,
+    };
+    let Some(song) = song else {
+        match path {
+            Some(path) => println!("{} is not a song in the database.", path),
+            None => println!("Not playing."),
+        }
+        return Ok(());
+    };
+    let keys = Group::Album.tags(&Tags::from_song(&song));
+    let Some(keys) = keys else {
+        println!("The song is not part of an album.");
+        return Ok(());
+    };
+    let songs = album::songs(raw, Group::Album, &Tags::from_song(&song), keys)?;
+
+    // Tags which MPD does not know, such as the catalog number, are read from
+    // the raw comments of the song.
+    let raw_comments = client
+        .readcomments(&song.file)?
+        .collect::<Result<Vec<_>, _>>()?;
+    let tags = songs
+        .iter()
+        .map(|s| match s.file == song.file {
+            true => Tags::from_song_and_raw_comments(s, raw_comments.clone()),
+            false => Tags::from_song(s),
+        })
+        .collect::<Vec<_>>();
+    let values = |tag: &str| distinct(tags.iter().flat_map(|t| t.get(tag)).map(str::to_string));
+
+    let discs = distinct(
+        tags.iter()
+            .flat_map(|t| t.get("disc"))
+            .filter_map(sort::number),
+    )
+    .len()
+    .max(1);
+    let duration = songs.iter().filter_map(|s| s.duration).sum::<Duration>();
+    let formats = values("format");
+    let cover = has_cover(raw, &song.file)?;
+
+    if json {
+        let tags = conf
+            .album_tags
+            .iter()
+            .map(|Tag { tag, .. }| {
+                let values = values(tag).into_iter().map(Into::into).collect();
+                (tag.to_lowercase(), json::Value::Array(values))
+            })
+            .collect();
+        let files = songs.iter().map(|s| s.file.as_str().into()).collect();
+        let formats = formats.into_iter().map(Into::into).collect();
+        let info = json::Value::object(vec![
+            ("tags", json::Value::object(tags)),
+            ("discs", json::Value::from(discs as i64)),
+            ("tracks", json::Value::from(songs.len() as i64)),
+            ("duration", duration.as_secs_f64().into()),
+            ("formats", json::Value::Array(formats)),
+            ("cover", cover.into()),
+            ("files", json::Value::Array(files)),
+        ]);
+        println!("{}", info);
+        return Ok(());
+    }
+
+    let fields = conf
+        .album_tags
+        .iter()
+        .filter_map(|Tag { tag, label }| {
+            let values = values(tag);
+            if values.is_empty() {
+                return None;
+            }
+            Some((label.as_ref().unwrap_or(tag).clone(), values.join(", ")))
+        })
+        .chain([
+            ("Discs".to_string(), discs.to_string()),
+            ("Tracks".to_string(), songs.len().to_string()),
+            ("Duration".to_string(), duration_format(&duration)),
+            ("Format".to_string(), formats.join(", ")),
+            (
+                "Cover art".to_string(),
+                if cover { "yes" } else { "no" }.into(),
+            ),
+        ])
+        .collect::<Vec<_>>();
+    let rows = fields
+        .iter()
+        .map(|(label, value)| {
+            Row::new(vec![
+                FormattedString::new(label).style(theme().tag_label),
+                FormattedString::new(value).style(theme().tag_value),
+            ])
+        })
+        .collect::<Vec<_>>();
+    let header = Tags::from_song(&song)
+        .joined(&keys, " - ")
+        .unwrap_or_default();
+    println!(
+        "{}\n\n{}",
+        FormattedString::new(&header).style(theme().header),
+        Table::new(&rows)
+    );
+    Ok(())
+}
+
+// The values in the order they first appear, without duplicates.
+fn distinct<T: Clone + Eq + std::hash::Hash>(values: impl Iterator<Item = T>) -> Vec<T> {
+    let mut seen = HashSet::new();
+    values.filter(|v| seen.insert(v.clone())).collect()
+}
+
+// Whether MPD finds cover art for the song, in its directory or embedded in
+// the file. Only the first chunk of the image is read.
+fn has_cover(raw: &mut raw::Connection, file: &str) -> Result<bool, Error> {
+    for command in ["albumart", "readpicture"] {
+        match raw.binary_command(command, &[file, "0"]) {
+            Ok(data) if !data.is_empty() => return Ok(true),
+            Ok(_) => (),
+            // No cover art, or readpicture is not supported by MPD.
+            Err(Error::Mpd(MpdError::Server(_))) => (),
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(false)
+}
@@ -8,9 +8,9 @@ pub fn fetch(client: &mut Client, song_path: Option<&str>, output: &str) -> Resu
     client.binarylimit(4_000_000)?;
 
     let album_art = if let Some(path) = song_path {
-        client.albumart(&*path)?
+        client.albumart(path)?
     } else if let Some(song) = client.currentsong()? {
-        client.albumart(&*song.file)?
+        client.albumart(&song.file)?
     } else {
         println!("No song specified and no song is currently playing.");
         exit(1);
@@ -22,7 +22,7 @@ pub fn fetch(client: &mut Client, song_path: Option<&str>, output: &str) -> Resu
     } else {
         File::create(output)
             .context("creating albumart file")?
-            .write_all(&*album_art)
+            .write_all(&album_art)
             .context("writing albumart to file")?;
     };
     Ok(())
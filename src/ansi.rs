@@ -1,14 +1,220 @@
 use std::env;
 use std::fmt;
 use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::OnceLock;
 
-#[allow(dead_code)]
-#[derive(Clone, Copy)]
-pub enum Style {
-    Bold = 1,
-    Faint = 2,
-    Underline = 4,
-    Default = 0,
+// Text attributes and colours, as SGR escape sequences. The attributes are a
+// bit set, where bit n corresponds to SGR parameter n.
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug)]
+pub struct Style {
+    attributes: u8,
+    pub foreground: Option<Color>,
+    pub background: Option<Color>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Color {
+    // One of the 16 colours of the terminal palette.
+    Basic(u8),
+    // A colour in the 256 colour palette.
+    Indexed(u8),
+    Rgb(u8, u8, u8),
+}
+
+static ATTRIBUTES: &[(&str, u8)] = &[
+    ("bold", 1),
+    ("faint", 2),
+    ("italic", 3),
+    ("underline", 4),
+    ("reverse", 7),
+];
+
+static COLORS: &[&str] = &[
+    "black", "red", "green", "yellow", "blue", "magenta", "cyan", "white",
+];
+
+impl Style {
+    pub const BOLD: Style = Style::attribute(1);
+    #[allow(dead_code)]
+    pub const FAINT: Style = Style::attribute(2);
+
+    const fn attribute(code: u8) -> Style {
+        Style {
+            attributes: 1 << code,
+            foreground: None,
+            background: None,
+        }
+    }
+
+    fn sgr_parameters(&self) -> Vec<String> {
+        let mut parameters = (1..8)
+            .filter(|code| self.attributes & (1 << code) != 0)
+            .map(|code| code.to_string())
+            .collect::<Vec<_>>();
+        if let Some(color) = self.foreground {
+            parameters.push(color.sgr_parameter(30));
+        }
+        if let Some(color) = self.background {
+            parameters.push(color.sgr_parameter(40));
+        }
+        parameters
+    }
+}
+
+impl Color {
+    // base is 30 for foreground and 40 for background colours.
+    fn sgr_parameter(&self, base: u8) -> String {
+        match *self {
+            Color::Basic(n) if n < 8 => (base + n).to_string(),
+            Color::Basic(n) => (base + 60 + n - 8).to_string(),
+            Color::Indexed(n) => format!("{};5;{}", base + 8, n),
+            Color::Rgb(r, g, b) => format!("{};2;{};{};{}", base + 8, r, g, b),
+        }
+    }
+}
+
+// Parse a style such as "bold red", "underline 208 on black" or
+// "rgb(255,128,0) on bright-black". Colours are one of the 16 named colours,
+// a number in the 256 colour palette, or rgb(r,g,b). A colour following "on"
+// is the background colour.
+impl std::str::FromStr for Style {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut style = Style::default();
+        let mut background = false;
+        for word in words(&s.to_lowercase()) {
+            if word == "on" {
+                background = true;
+            } else if word == "none" || word == "default" {
+                continue;
+            } else if let Some((_, code)) = ATTRIBUTES.iter().find(|(a, _)| *a == word) {
+                style.attributes |= 1 << code;
+            } else {
+                let color = word
+                    .parse::<Color>()
+                    .map_err(|_| format!("unknown style: {}", word))?;
+                if background {
+                    style.background = Some(color);
+                } else {
+                    style.foreground = Some(color);
+                }
+            }
+        }
+        Ok(style)
+    }
+}
+
+// Split s at whitespace, except inside parentheses.
+fn words(s: &str) -> Vec<String> {
+    let mut words = vec![String::new()];
+    let mut depth = 0;
+    for c in s.chars() {
+        match c {
+            '(' => depth += 1,
+            ')' => depth -= 1,
+            _ => (),
+        }
+        if !c.is_whitespace() {
+            words.last_mut().expect("words can not be empty").push(c);
+        } else if depth == 0 && words.last().is_some_and(|w| !w.is_empty()) {
+            words.push(String::new());
+        }
+    }
+    words.into_iter().filter(|w| !w.is_empty()).collect()
+}
+
+impl std::str::FromStr for Color {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Some(n) = COLORS.iter().position(|c| *c == s) {
+            Ok(Color::Basic(n as u8))
+        } else if let Some(n) = s
+            .strip_prefix("bright-")
+            .and_then(|c| COLORS.iter().position(|n| *n == c))
+        {
+            Ok(Color::Basic(n as u8 + 8))
+        } else if let Some(rgb) = s.strip_prefix("rgb(").and_then(|s| s.strip_suffix(')')) {
+            match rgb
+                .split(',')
+                .map(|c| c.trim().parse::<u8>())
+                .collect::<Result<Vec<_>, _>>()
+                .as_deref()
+            {
+                Ok([r, g, b]) => Ok(Color::Rgb(*r, *g, *b)),
+                _ => Err(()),
+            }
+        } else {
+            s.parse().map(Color::Indexed).map_err(|_| ())
+        }
+    }
+}
+
+// Styles of the elements in the output of davis, configured in the theme
+// section of the config file.
+#[derive(Clone)]
+pub struct Theme {
+    pub header: Style,
+    pub current: Style,
+    pub tag_label: Style,
+    pub tag_value: Style,
+    pub playing: Style,
+    pub paused: Style,
+    pub stopped: Style,
+    pub progress: Style,
+    pub progress_remaining: Style,
+    pub directory: Style,
+    pub playlist: Style,
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Theme {
+            header: Style::BOLD,
+            current: Style::BOLD,
+            tag_label: Style::BOLD,
+            tag_value: Style::default(),
+            playing: Style::default(),
+            paused: Style::default(),
+            stopped: Style::default(),
+            progress: Style::default(),
+            progress_remaining: Style::default(),
+            directory: Style::default(),
+            playlist: Style::default(),
+        }
+    }
+}
+
+impl Theme {
+    pub fn element_mut(&mut self, name: &str) -> Option<&mut Style> {
+        Some(match name {
+            "header" => &mut self.header,
+            "current" => &mut self.current,
+            "tag_label" => &mut self.tag_label,
+            "tag_value" => &mut self.tag_value,
+            "playing" => &mut self.playing,
+            "paused" => &mut self.paused,
+            "stopped" => &mut self.stopped,
+            "progress" => &mut self.progress,
+            "progress_remaining" => &mut self.progress_remaining,
+            "directory" => &mut self.directory,
+            "playlist" => &mut self.playlist,
+            _ => return None,
+        })
+    }
+}
+
+static THEME: OnceLock<Theme> = OnceLock::new();
+
+pub fn set_theme(theme: Theme) {
+    if THEME.set(theme).is_err() {
+        log::warn!("Theme was already set, ignoring.");
+    }
+}
+
+pub fn theme() -> &'static Theme {
+    THEME.get_or_init(Theme::default)
 }
 
 pub struct FormattedString<'a> {
@@ -35,16 +241,17 @@ impl<'a> FormattedString<'a> {
 impl<'a> fmt::Display for FormattedString<'a> {
     fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
         if is_dumb_terminal() {
-            formatter.pad(&*self.string)?;
+            formatter.pad(self.string)?;
             return Ok(());
         }
 
-        if let Some(style) = self.style {
-            write!(formatter, "\x1B[{}m", style as u8)?;
-            formatter.pad(&*self.string)?;
-            write!(formatter, "\x1B[{}m", Style::Default as u8)?;
-        } else {
-            formatter.pad(&*self.string)?;
+        match self.style.map(|s| s.sgr_parameters()) {
+            Some(parameters) if !parameters.is_empty() => {
+                write!(formatter, "\x1B[{}m", parameters.join(";"))?;
+                formatter.pad(self.string)?;
+                write!(formatter, "\x1B[0m")?;
+            }
+            _ => formatter.pad(self.string)?,
         }
         Ok(())
     }
@@ -57,3 +264,49 @@ pub fn is_dumb_terminal() -> bool {
 
     is_dumb || has_no_color || PLAIN_FORMATTING.load(Ordering::Relaxed)
 }
+
+// The width of the terminal on stdout, or None if stdout is not a terminal.
+// The COLUMNS environment variable takes precedence, if set.
+pub fn terminal_width() -> Option<usize> {
+    terminal_size("COLUMNS", |size| size.ws_col)
+}
+
+// The height of the terminal on stdout, with LINES taking precedence.
+pub fn terminal_height() -> Option<usize> {
+    terminal_size("LINES", |size| size.ws_row)
+}
+
+fn terminal_size(var: &str, get: impl Fn(&libc::winsize) -> u16) -> Option<usize> {
+    if let Some(value) = env::var(var).ok().and_then(|c| c.parse().ok()) {
+        return Some(value);
+    }
+    let mut size = libc::winsize {
+        ws_row: 0,
+        ws_col: 0,
+        ws_xpixel: 0,
+        ws_ypixel: 0,
+    };
+    let result = unsafe { libc::ioctl(libc::STDOUT_FILENO, libc::TIOCGWINSZ, &mut size) };
+    if result == 0 && get(&size) > 0 {
+        Some(usize::from(get(&size)))
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse_styles() {
+        let style = |s: &str| s.parse::<Style>().unwrap().sgr_parameters().join(";");
+        assert_eq!(style("bold"), "1");
+        assert_eq!(style("underline red"), "4;31");
+        assert_eq!(style("bright-red on blue"), "91;44");
+        assert_eq!(style("208 on rgb(0, 16,255)"), "38;5;208;48;2;0;16;255");
+        assert_eq!(style("none"), "");
+        assert!("bold purple".parse::<Style>().is_err());
+        assert!("rgb(1,2)".parse::<Style>().is_err());
+    }
+}
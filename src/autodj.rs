@@ -0,0 +1,272 @@
+use crate::album::{self, Group};
+use crate::cli::SearchQuery;
+use crate::error::Error;
+use crate::random::Random;
+use crate::raw;
+use crate::sort;
+use crate::tags::Tags;
+use mpdrs::lsinfo::LsInfoResponse;
+use mpdrs::Song;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::time::Duration;
+
+pub struct Options {
+    // Keep at least this many songs after the current one.
+    pub upcoming: usize,
+    // Never pick any of this many songs last added or played, if possible.
+    pub history: usize,
+    // Add whole albums instead of single songs.
+    pub albums: bool,
+    // Remove played songs, keeping this many before the current one.
+    pub keep: Option<usize>,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Options {
+            upcoming: 5,
+            history: 200,
+            albums: false,
+            keep: None,
+        }
+    }
+}
+
+// Keep adding random songs or albums matching query to the queue, whenever
+// it is running out of songs, until interrupted.
+pub fn autodj(
+    client: &mut mpdrs::Client,
+    raw: &mut raw::Connection,
+    query: &SearchQuery,
+    options: &Options,
+) -> Result<(), Error> {
+    let mut candidates = matching(raw, query)?;
+    if candidates.is_empty() {
+        println!("No songs match the query.");
+        return Ok(());
+    }
+    let mut random = Random::from_clock();
+    let mut history = History::new(options.history);
+    for song in client.queue()? {
+        history.push(&song.file);
+    }
+    loop {
+        let status = client.status()?;
+        let position = status.song.map(|s| s.pos as usize);
+        if let Some(song) = client.currentsong()? {
+            history.push(&song.file);
+        }
+        let upcoming = match position {
+            Some(position) => (status.queue_len as usize).saturating_sub(position + 1),
+            None => status.queue_len as usize,
+        };
+        if upcoming < options.upcoming {
+            let n = options.upcoming - upcoming;
+            let songs = if options.albums {
+                let mut songs = vec![];
+                for album in pick_albums(&candidates, &history, n, &mut random) {
+                    songs.extend(whole_album(raw, album)?);
+                }
+                songs
+            } else {
+                pick_songs(&candidates, &history, n, &mut random)
+            };
+            log::trace!("Adding {} songs to the queue.", songs.len());
+            for song in &songs {
+                history.push(&song.file);
+            }
+            let commands = songs
+                .iter()
+                .map(|song| ("add", vec![&*song.file]))
+                .collect::<Vec<_>>();
+            raw.command_list(&commands)?;
+        }
+        match (options.keep, position) {
+            (Some(keep), Some(position)) if position > keep => {
+                raw.command("delete", &[&format!("0:{}", position - keep)])?;
+            }
+            _ => (),
+        }
+
+        let changed = loop {
+            let subsystems = ["playlist", "player", "database"];
+            if let Some(changed) = raw.idle(&subsystems, Duration::from_secs(3600))? {
+                break changed;
+            }
+        };
+        if changed.iter().any(|s| s == "database") {
+            candidates = matching(raw, query)?;
+            if candidates.is_empty() {
+                println!("No songs match the query.");
+                return Ok(());
+            }
+        }
+    }
+}
+
+// The songs matching query, compared as by search, or the whole database for
+// an empty query.
+pub fn matching(raw: &mut raw::Connection, query: &SearchQuery) -> Result<Vec<Song>, Error> {
+    let args = query.to_args();
+    if !args.is_empty() {
+        return raw.songs("search", &args);
+    }
+    Ok(raw
+        .entries("listallinfo", &[])?
+        .into_iter()
+        .filter_map(|entry| match entry {
+            LsInfoResponse::Song(song) => Some(song),
+            _ => None,
+        })
+        .collect())
+}
+
+// The songs grouped by album, in the order the albums first appear, with the
+// songs of every album by disc and track number.
+pub fn albums(songs: &[Song]) -> Vec<Vec<Song>> {
+    let mut albums: Vec<Vec<Song>> = vec![];
+    let mut index = HashMap::new();
+    for song in songs {
+        let key = sort::album(&Tags::from_song(song));
+        let i = *index.entry(key).or_insert_with(|| {
+            albums.push(vec![]);
+            albums.len() - 1
+        });
+        albums[i].push(song.clone());
+    }
+    for album in &mut albums {
+        sort::natural(album);
+    }
+    albums
+}
+
+// n random songs, preferring those which are not in the history.
+fn pick_songs(candidates: &[Song], history: &History, n: usize, random: &mut Random) -> Vec<Song> {
+    let mut fresh = candidates
+        .iter()
+        .filter(|song| !history.contains(&song.file))
+        .collect::<Vec<_>>();
+    if fresh.len() < n {
+        log::trace!("Too few songs outside the history, picking from all songs.");
+        fresh = candidates.iter().collect();
+    }
+    random.shuffle(&mut fresh);
+    fresh.into_iter().take(n).cloned().collect()
+}
+
+// Random albums with at least n songs in total, preferring those which are
+// not in the history. Only the songs of an album among the candidates are
+// included, see whole_album.
+fn pick_albums(
+    candidates: &[Song],
+    history: &History,
+    n: usize,
+    random: &mut Random,
+) -> Vec<Vec<Song>> {
+    let mut all = albums(candidates);
+    if all
+        .iter()
+        .any(|album| album.iter().all(|song| !history.contains(&song.file)))
+    {
+        all.retain(|album| album.iter().all(|song| !history.contains(&song.file)));
+    } else {
+        log::trace!("No albums outside the history, picking from all albums.");
+    }
+    random.shuffle(&mut all);
+    let mut count = 0;
+    all.into_iter()
+        .take_while(|album| {
+            let more = count < n;
+            count += album.len();
+            more
+        })
+        .collect()
+}
+
+// All songs of the album of songs, which may be only those matching a query,
+// in order. Songs without an album are returned as they are.
+pub fn whole_album(raw: &mut raw::Connection, songs: Vec<Song>) -> Result<Vec<Song>, Error> {
+    let Some(first) = songs.first() else {
+        return Ok(songs);
+    };
+    let tags = Tags::from_song(first);
+    let Some(keys) = Group::Album.tags(&tags) else {
+        return Ok(songs);
+    };
+    let album = album::songs(raw, Group::Album, &tags, keys)?;
+    Ok(if album.is_empty() { songs } else { album })
+}
+
+// The files of the songs last added or played, most recent last.
+struct History {
+    files: VecDeque<String>,
+    // The same files, for looking them up.
+    set: HashSet<String>,
+    size: usize,
+}
+
+impl History {
+    fn new(size: usize) -> History {
+        History {
+            files: VecDeque::new(),
+            set: HashSet::new(),
+            size,
+        }
+    }
+
+    fn push(&mut self, file: &str) {
+        if !self.set.insert(file.to_string()) {
+            self.files.retain(|f| f != file);
+        }
+        self.files.push_back(file.to_string());
+        while self.files.len() > self.size {
+            if let Some(file) = self.files.pop_front() {
+                self.set.remove(&file);
+            }
+        }
+    }
+
+    fn contains(&self, file: &str) -> bool {
+        self.set.contains(file)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn song(file: &str, album: &str) -> Song {
+        Song {
+            file: file.into(),
+            tags: vec![("Album".into(), album.into())],
+            ..Song::default()
+        }
+    }
+
+    #[test]
+    fn picking() {
+        let candidates = vec![song("a1", "A"), song("a2", "A"), song("b1", "B")];
+        let mut random = Random::from_seed(1);
+        let mut history = History::new(2);
+        history.push("a1");
+        history.push("a2");
+
+        let files = |songs: Vec<Song>| songs.into_iter().map(|s| s.file).collect::<Vec<_>>();
+        assert_eq!(
+            files(pick_songs(&candidates, &history, 1, &mut random)),
+            vec!["b1"]
+        );
+        assert_eq!(
+            files(pick_albums(&candidates, &history, 1, &mut random).concat()),
+            vec!["b1"]
+        );
+        // Too few songs outside the history.
+        assert_eq!(pick_songs(&candidates, &history, 2, &mut random).len(), 2);
+        assert_eq!(pick_albums(&candidates, &history, 2, &mut random).len(), 1);
+
+        // The oldest songs leave the history.
+        history.push("b1");
+        assert!(!history.contains("a1"));
+        assert!(history.contains("a2"));
+    }
+}
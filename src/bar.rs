@@ -0,0 +1,159 @@
+use crate::config::Config;
+use crate::error::Error;
+use crate::format::Template;
+use crate::json;
+use crate::tags::Tags;
+use crate::unicode;
+use mpdrs::idle::Idle;
+use std::str::FromStr;
+
+static DEFAULT_FORMAT: &str = "[%artist% - ]%title%|%file%";
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Style {
+    Waybar,
+    I3bar,
+    Polybar,
+    Tmux,
+}
+
+impl FromStr for Style {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "waybar" => Ok(Style::Waybar),
+            "i3bar" => Ok(Style::I3bar),
+            "polybar" => Ok(Style::Polybar),
+            "tmux" => Ok(Style::Tmux),
+            _ => Err(format!("unknown bar style: {}", s)),
+        }
+    }
+}
+
+struct State {
+    text: String,
+    tooltip: String,
+    class: &'static str,
+    percentage: Option<f64>,
+}
+
+pub fn run(client: &mut mpdrs::Client, conf: &Config, style: Style) -> Result<(), Error> {
+    let template =
+        Template::from_config_or_str(conf, conf.bar.format.as_deref().unwrap_or(DEFAULT_FORMAT))?;
+
+    if style == Style::I3bar {
+        println!("{{\"version\":1}}\n[");
+    }
+
+    let mut first = true;
+    loop {
+        let state = state(client, conf, &template)?;
+        println!("{}", line(style, &state, first));
+        first = false;
+        client.wait(&[mpdrs::Subsystem::Player])?;
+    }
+}
+
+fn state(client: &mut mpdrs::Client, conf: &Config, template: &Template) -> Result<State, Error> {
+    let status = client.status()?;
+    let song = match (status.state, client.currentsong()?) {
+        (mpdrs::State::Stop, _) | (_, None) => {
+            return Ok(State {
+                text: conf.bar.stop_icon.clone(),
+                tooltip: "Stopped".to_string(),
+                class: "stopped",
+                percentage: None,
+            })
+        }
+        (_, Some(song)) => song,
+    };
+
+    let (icon, class) = match status.state {
+        mpdrs::State::Pause => (&conf.bar.pause_icon, "paused"),
+        _ => (&conf.bar.play_icon, "playing"),
+    };
+    let tags = template.tags_for(client, &song)?;
+    let title = template.render(&song, &tags).replace('\n', " ");
+    let text = match conf.bar.max_length {
+        Some(max) => unicode::truncate(&title, max),
+        None => title.clone(),
+    };
+    let tooltip = match Tags::from_song(&song).get_option_joined("album") {
+        Some(album) => format!("{}\n{}", title, album),
+        None => title,
+    };
+    let percentage = match (status.elapsed, status.duration) {
+        (Some(elapsed), Some(duration)) if !duration.is_zero() => {
+            Some((100. * elapsed.as_secs_f64() / duration.as_secs_f64()).round())
+        }
+        _ => None,
+    };
+
+    Ok(State {
+        text: [icon.as_str(), &text]
+            .iter()
+            .filter(|s| !s.is_empty())
+            .copied()
+            .collect::<Vec<_>>()
+            .join(" "),
+        tooltip,
+        class,
+        percentage,
+    })
+}
+
+fn line(style: Style, state: &State, first: bool) -> String {
+    match style {
+        Style::Waybar => json::Value::object(vec![
+            ("text", state.text.as_str().into()),
+            ("tooltip", state.tooltip.as_str().into()),
+            ("class", state.class.into()),
+            ("percentage", state.percentage.into()),
+        ])
+        .to_string(),
+        Style::I3bar => {
+            let blocks = json::Value::Array(vec![json::Value::object(vec![
+                ("name", "davis".into()),
+                ("instance", state.class.into()),
+                ("full_text", state.text.as_str().into()),
+                ("markup", "none".into()),
+            ])]);
+            if first {
+                blocks.to_string()
+            } else {
+                format!(",{}", blocks)
+            }
+        }
+        Style::Polybar => state.text.replace("%{", "%%{"),
+        Style::Tmux => state.text.replace('#', "##"),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn state() -> State {
+        State {
+            text: "▶ A #1 %{F}".into(),
+            tooltip: "A\nB".into(),
+            class: "playing",
+            percentage: Some(50.),
+        }
+    }
+
+    #[test]
+    fn styles() {
+        assert_eq!(
+            line(Style::Waybar, &state(), true),
+            r#"{"text":"▶ A #1 %{F}","tooltip":"A\nB","class":"playing","percentage":50}"#
+        );
+        assert_eq!(
+            line(Style::I3bar, &state(), false),
+            r#",[{"name":"davis","instance":"playing","full_text":"▶ A #1 %{F}","markup":"none"}]"#
+        );
+        assert_eq!(line(Style::Polybar, &state(), true), "▶ A #1 %%{F}");
+        assert_eq!(line(Style::Tmux, &state(), true), "▶ A ##1 %{F}");
+    }
+}
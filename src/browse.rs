@@ -0,0 +1,711 @@
+use crate::ansi::{terminal_height, terminal_width, theme, FormattedString, Style};
+use crate::error::{Error, WithContext};
+use crate::raw;
+use crate::sort;
+use crate::status::duration_format;
+use crate::table::{Align, Row, Table};
+use crate::tags::Tags;
+use crate::unicode;
+use mpdrs::lsinfo::LsInfoResponse;
+use mpdrs::Song;
+use std::collections::BTreeSet;
+use std::io::Write;
+
+const HINTS: &str = "a add  i insert  p play  space mark  * mark all  / filter  q quit";
+
+// How to drill through the library: by the values of a list of tags, and
+// then the songs, or by directory.
+pub enum Hierarchy {
+    Tags(Vec<String>),
+    Directories,
+}
+
+enum Item {
+    // A value of the tag of the level.
+    Tag(String),
+    Directory(String),
+    Playlist(String),
+    Song(Song),
+}
+
+impl Item {
+    fn label(&self) -> String {
+        let name = |path: &str| path.rsplit('/').next().unwrap_or(path).to_string();
+        match self {
+            Item::Tag(value) if value.is_empty() => "(none)".to_string(),
+            Item::Tag(value) => value.clone(),
+            Item::Directory(path) => format!("{}/", name(path)),
+            Item::Playlist(path) => format!("{}@", name(path)),
+            Item::Song(song) => {
+                let track = Tags::from_song(song)
+                    .get_option_joined("track")
+                    .and_then(|t| sort::number(&t));
+                match (&song.title, track) {
+                    (Some(title), Some(track)) => format!("{:02}. {}", track, title),
+                    (Some(title), None) => title.clone(),
+                    _ => name(&song.file),
+                }
+            }
+        }
+    }
+
+    fn style(&self) -> Style {
+        match self {
+            Item::Directory(_) => theme().directory,
+            Item::Playlist(_) => theme().playlist,
+            _ => Style::default(),
+        }
+    }
+}
+
+// A list of items, such as the artists of a genre, or the contents of a
+// directory.
+struct Level {
+    title: String,
+    // The tag filters leading to the level, in the tag hierarchy.
+    filters: Vec<(String, String)>,
+    items: Vec<Item>,
+    labels: Vec<String>,
+    filter: String,
+    // Indices into items of the items matching the filter.
+    visible: Vec<usize>,
+    // Index into visible.
+    cursor: usize,
+    offset: usize,
+    marked: BTreeSet<usize>,
+}
+
+impl Level {
+    fn new(title: String, filters: Vec<(String, String)>, items: Vec<Item>) -> Level {
+        let labels = items.iter().map(Item::label).collect();
+        let mut level = Level {
+            title,
+            filters,
+            items,
+            labels,
+            filter: String::new(),
+            visible: vec![],
+            cursor: 0,
+            offset: 0,
+            marked: BTreeSet::new(),
+        };
+        level.apply_filter();
+        level
+    }
+
+    // Show only the items containing the filter, ignoring case.
+    fn apply_filter(&mut self) {
+        let current = self.visible.get(self.cursor).copied();
+        let filter = self.filter.to_lowercase();
+        self.visible = (0..self.items.len())
+            .filter(|i| self.labels[*i].to_lowercase().contains(&filter))
+            .collect();
+        self.cursor = current
+            .and_then(|c| self.visible.iter().position(|i| *i == c))
+            .unwrap_or(0);
+    }
+
+    fn current(&self) -> Option<usize> {
+        self.visible.get(self.cursor).copied()
+    }
+
+    fn move_cursor(&mut self, by: isize) {
+        let last = self.visible.len().saturating_sub(1) as isize;
+        self.cursor = (self.cursor as isize + by).clamp(0, last) as usize;
+    }
+
+    // Scroll as little as needed to show the cursor within rows. Returns the
+    // index into visible of the first row shown.
+    fn scroll(&mut self, rows: usize) -> usize {
+        self.offset = self.offset.min(self.cursor);
+        if self.cursor >= self.offset + rows {
+            self.offset = self.cursor + 1 - rows;
+        }
+        self.offset
+    }
+
+    // The marked items, or the current item if none are marked.
+    fn targets(&self) -> Vec<usize> {
+        if self.marked.is_empty() {
+            self.current().into_iter().collect()
+        } else {
+            self.marked.iter().copied().collect()
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Key {
+    Up,
+    Down,
+    Left,
+    Right,
+    PageUp,
+    PageDown,
+    Home,
+    End,
+    Enter,
+    Backspace,
+    Escape,
+    Char(char),
+    // A control character, such as Ctrl('c') for ^C.
+    Ctrl(char),
+}
+
+// Where to add songs in the queue.
+#[derive(Clone, Copy)]
+enum Placement {
+    End,
+    AfterCurrent,
+    EndAndPlay,
+}
+
+struct Browser {
+    hierarchy: Hierarchy,
+    levels: Vec<Level>,
+    // Set while typing a filter.
+    input: bool,
+    message: Option<String>,
+}
+
+// Browse the library until the user quits.
+pub fn browse(
+    client: &mut mpdrs::Client,
+    raw: &mut raw::Connection,
+    hierarchy: Hierarchy,
+) -> Result<(), Error> {
+    let mut browser = Browser {
+        hierarchy,
+        levels: vec![],
+        input: false,
+        message: None,
+    };
+    let root = browser.load(raw, "Library".to_string(), vec![], "")?;
+    browser.levels.push(root);
+
+    let terminal = Terminal::new()?;
+    loop {
+        let height = terminal_height().unwrap_or(24);
+        let width = terminal_width().unwrap_or(80);
+        terminal.draw(&browser.render(height, width))?;
+        for key in terminal.read_keys()? {
+            if !browser.handle(key, height, client, raw)? {
+                return Ok(());
+            }
+        }
+    }
+}
+
+impl Browser {
+    // Load the level below the given tag filters, or at the given path.
+    fn load(
+        &self,
+        raw: &mut raw::Connection,
+        title: String,
+        filters: Vec<(String, String)>,
+        path: &str,
+    ) -> Result<Level, Error> {
+        let args = filters
+            .iter()
+            .flat_map(|(tag, value)| [&**tag, &**value])
+            .collect::<Vec<_>>();
+        let items = match &self.hierarchy {
+            Hierarchy::Tags(tags) if filters.len() < tags.len() => {
+                let mut list_args = vec![&*tags[filters.len()]];
+                list_args.extend(&args);
+                raw.command("list", &list_args)?
+                    .into_iter()
+                    .map(|(_, value)| Item::Tag(value))
+                    .collect()
+            }
+            Hierarchy::Tags(_) => {
+                let mut songs = raw.songs("find", &args)?;
+                sort::natural(&mut songs);
+                songs.into_iter().map(Item::Song).collect()
+            }
+            Hierarchy::Directories => raw
+                .entries("lsinfo", &[path])?
+                .into_iter()
+                .map(|entry| match entry {
+                    LsInfoResponse::Song(song) => Item::Song(song),
+                    LsInfoResponse::Directory { path, .. } => Item::Directory(path),
+                    LsInfoResponse::Playlist { path, .. } => Item::Playlist(path),
+                })
+                .collect(),
+        };
+        Ok(Level::new(title, filters, items))
+    }
+
+    fn level(&mut self) -> &mut Level {
+        self.levels.last_mut().expect("there is always a level")
+    }
+
+    // Handle a key press. Returns false to quit.
+    fn handle(
+        &mut self,
+        key: Key,
+        height: usize,
+        client: &mut mpdrs::Client,
+        raw: &mut raw::Connection,
+    ) -> Result<bool, Error> {
+        self.message = None;
+        let page = height.saturating_sub(3).max(1) as isize;
+        if self.input {
+            match key {
+                Key::Char(c) => self.level().filter.push(c),
+                Key::Backspace => {
+                    self.level().filter.pop();
+                }
+                Key::Enter => self.input = false,
+                Key::Escape => {
+                    self.input = false;
+                    self.level().filter.clear();
+                }
+                _ => (),
+            }
+            if matches!(
+                key,
+                Key::Char(_) | Key::Backspace | Key::Enter | Key::Escape
+            ) {
+                self.level().apply_filter();
+                return Ok(true);
+            }
+        }
+        match key {
+            Key::Char('q') | Key::Ctrl('c') | Key::Ctrl('d') => return Ok(false),
+            Key::Up | Key::Char('k') => self.level().move_cursor(-1),
+            Key::Down | Key::Char('j') => self.level().move_cursor(1),
+            Key::PageUp => self.level().move_cursor(-page),
+            Key::PageDown => self.level().move_cursor(page),
+            Key::Home | Key::Char('g') => self.level().move_cursor(isize::MIN / 2),
+            Key::End | Key::Char('G') => self.level().move_cursor(isize::MAX / 2),
+            Key::Char('/') => self.input = true,
+            Key::Escape => {
+                self.level().filter.clear();
+                self.level().apply_filter();
+            }
+            Key::Left | Key::Backspace | Key::Char('h') if self.levels.len() > 1 => {
+                self.levels.pop();
+            }
+            Key::Right | Key::Char('l') | Key::Enter => self.open(raw, client, key)?,
+            Key::Char(' ') => {
+                let level = self.level();
+                if let Some(current) = level.current() {
+                    if !level.marked.remove(&current) {
+                        level.marked.insert(current);
+                    }
+                    level.move_cursor(1);
+                }
+            }
+            Key::Char('*') => {
+                let level = self.level();
+                if level.visible.iter().all(|i| level.marked.contains(i)) {
+                    level.marked.clear();
+                } else {
+                    level.marked.extend(level.visible.iter().copied());
+                }
+            }
+            Key::Char('a') => self.queue(client, raw, Placement::End),
+            Key::Char('i') => self.queue(client, raw, Placement::AfterCurrent),
+            Key::Char('p') => self.queue(client, raw, Placement::EndAndPlay),
+            _ => (),
+        }
+        Ok(true)
+    }
+
+    // Descend into the current item. Songs are played instead.
+    fn open(
+        &mut self,
+        raw: &mut raw::Connection,
+        client: &mut mpdrs::Client,
+        key: Key,
+    ) -> Result<(), Error> {
+        let level = self.levels.last().expect("there is always a level");
+        let Some(current) = level.current() else {
+            return Ok(());
+        };
+        let title = level.labels[current].clone();
+        let next = match &level.items[current] {
+            Item::Tag(value) => {
+                let tags = match &self.hierarchy {
+                    Hierarchy::Tags(tags) => tags,
+                    Hierarchy::Directories => unreachable!("tags only appear in the tag hierarchy"),
+                };
+                let mut filters = level.filters.clone();
+                filters.push((tags[filters.len()].clone(), value.clone()));
+                self.load(raw, title, filters, "")
+            }
+            Item::Directory(path) => {
+                let title = title.trim_end_matches('/').to_string();
+                self.load(raw, title, vec![], path)
+            }
+            Item::Song(_) | Item::Playlist(_) if key == Key::Enter => {
+                let marked = std::mem::take(&mut self.level().marked);
+                self.queue(client, raw, Placement::EndAndPlay);
+                self.level().marked = marked;
+                return Ok(());
+            }
+            Item::Song(_) | Item::Playlist(_) => return Ok(()),
+        };
+        match next {
+            Ok(next) => self.levels.push(next),
+            Err(e) => self.message = Some(e.to_string()),
+        }
+        Ok(())
+    }
+
+    // Add the marked items, or the current item, to the queue. Errors are
+    // shown in the status line.
+    fn queue(
+        &mut self,
+        client: &mut mpdrs::Client,
+        raw: &mut raw::Connection,
+        placement: Placement,
+    ) {
+        self.message = Some(match self.add_targets(client, raw, placement) {
+            Ok(0) => "Nothing to add.".to_string(),
+            Ok(n) => {
+                self.level().marked.clear();
+                format!("Added {} item{}.", n, if n == 1 { "" } else { "s" })
+            }
+            Err(e) => e.to_string().replace('\n', " "),
+        });
+    }
+
+    fn add_targets(
+        &self,
+        client: &mut mpdrs::Client,
+        raw: &mut raw::Connection,
+        placement: Placement,
+    ) -> Result<usize, Error> {
+        let level = self.levels.last().expect("there is always a level");
+        let targets = level.targets();
+        if targets.is_empty() {
+            return Ok(0);
+        }
+        let status = client.status()?;
+        let position = match placement {
+            Placement::End | Placement::EndAndPlay => None,
+            Placement::AfterCurrent => Some(status.song.map_or(0, |s| s.pos + 1).to_string()),
+        };
+        let tag = match &self.hierarchy {
+            Hierarchy::Tags(tags) => tags.get(level.filters.len()),
+            Hierarchy::Directories => None,
+        };
+        let mut commands = targets
+            .iter()
+            .map(|i| {
+                let (command, mut args) = match &level.items[*i] {
+                    Item::Tag(value) => {
+                        let mut args = level
+                            .filters
+                            .iter()
+                            .flat_map(|(tag, value)| [&**tag, &**value])
+                            .collect::<Vec<_>>();
+                        args.extend([&**tag.expect("tag items have a tag"), &**value]);
+                        if position.is_some() {
+                            args.push("position");
+                        }
+                        ("findadd", args)
+                    }
+                    Item::Directory(path) => ("add", vec![&**path]),
+                    Item::Song(song) => ("add", vec![&*song.file]),
+                    Item::Playlist(path) if position.is_some() => ("load", vec![&**path, "0:"]),
+                    Item::Playlist(path) => ("load", vec![&**path]),
+                };
+                args.extend(position.as_deref());
+                (command, args)
+            })
+            .collect::<Vec<_>>();
+        // Every item is inserted at the same position, so insert them in
+        // reverse to keep their order.
+        if position.is_some() {
+            commands.reverse();
+        }
+        raw.command_list(&commands)?;
+        if let Placement::EndAndPlay = placement {
+            raw.command("play", &[&status.queue_len.to_string()])?;
+        }
+        Ok(targets.len())
+    }
+
+    fn render(&mut self, height: usize, width: usize) -> String {
+        // The header and status lines take one line each.
+        let rows = height.saturating_sub(2).max(1);
+        let offset = self.level().scroll(rows);
+        let level = self.levels.last().expect("there is always a level");
+        let breadcrumbs = self
+            .levels
+            .iter()
+            .map(|l| &*l.title)
+            .collect::<Vec<_>>()
+            .join(" › ");
+        // The header and status lines are truncated, so that they do not
+        // wrap and push the rest of the screen out of place.
+        let breadcrumbs = unicode::truncate(&breadcrumbs, width);
+        let mut out = format!(
+            "{}\n",
+            FormattedString::new(&breadcrumbs).style(theme().header)
+        );
+
+        let fields = level
+            .visible
+            .iter()
+            .enumerate()
+            .skip(offset)
+            .take(rows)
+            .map(|(i, item)| {
+                let duration = match &level.items[*item] {
+                    Item::Song(song) => song.duration.map(|d| duration_format(&d)),
+                    _ => None,
+                };
+                // The cursor is marked with > in case styles are disabled.
+                let mark = match (i == level.cursor, level.marked.contains(item)) {
+                    (true, true) => ">+",
+                    (true, false) => "> ",
+                    (false, true) => " +",
+                    (false, false) => "  ",
+                };
+                (
+                    i == level.cursor,
+                    &level.items[*item],
+                    mark,
+                    duration.unwrap_or_default(),
+                    &level.labels[*item],
+                )
+            })
+            .collect::<Vec<_>>();
+        let table_rows = fields
+            .iter()
+            .map(|(current, item, mark, duration, label)| {
+                let style = if *current {
+                    theme().current
+                } else {
+                    item.style()
+                };
+                Row::new(vec![
+                    FormattedString::new(mark).style(style),
+                    FormattedString::new(duration).style(style),
+                    FormattedString::new(label).style(style),
+                ])
+            })
+            .collect::<Vec<_>>();
+        if table_rows.is_empty() {
+            out.push_str("No items.\n");
+        } else {
+            out.push_str(&format!(
+                "{}\n",
+                Table::new(&table_rows).align(1, Align::Right)
+            ));
+        }
+        for _ in table_rows.len().max(1)..rows {
+            out.push('\n');
+        }
+
+        let status = if self.input || !level.filter.is_empty() {
+            format!("/{}", level.filter)
+        } else if let Some(message) = &self.message {
+            message.clone()
+        } else {
+            HINTS.to_string()
+        };
+        out.push_str(&unicode::truncate(&status, width));
+        out
+    }
+}
+
+// Read keys from raw terminal input. An escape which is not followed by a
+// known sequence is the escape key.
+fn parse_keys(input: &str) -> Vec<Key> {
+    let mut keys = vec![];
+    let mut chars = input.chars().peekable();
+    while let Some(c) = chars.next() {
+        let key = match c {
+            '\x1B' if matches!(chars.peek(), Some('[' | 'O')) => {
+                chars.next();
+                let mut sequence = String::new();
+                while let Some(c) = chars.next_if(|c| c.is_ascii_digit() || *c == ';') {
+                    sequence.push(c);
+                }
+                match (chars.next(), &*sequence) {
+                    (Some('A'), _) => Key::Up,
+                    (Some('B'), _) => Key::Down,
+                    (Some('C'), _) => Key::Right,
+                    (Some('D'), _) => Key::Left,
+                    (Some('H'), _) | (Some('~'), "1" | "7") => Key::Home,
+                    (Some('F'), _) | (Some('~'), "4" | "8") => Key::End,
+                    (Some('~'), "5") => Key::PageUp,
+                    (Some('~'), "6") => Key::PageDown,
+                    _ => continue,
+                }
+            }
+            '\x1B' => Key::Escape,
+            '\r' | '\n' => Key::Enter,
+            '\x7F' | '\x08' => Key::Backspace,
+            c if (c as u32) < 0x20 => Key::Ctrl(char::from(c as u8 + b'`')),
+            c => Key::Char(c),
+        };
+        keys.push(key);
+    }
+    keys
+}
+
+extern "C" fn resized(_: libc::c_int) {}
+
+// The terminal in raw mode, on the alternate screen, until dropped.
+struct Terminal {
+    original: libc::termios,
+}
+
+impl Terminal {
+    fn new() -> Result<Terminal, Error> {
+        if unsafe { libc::isatty(libc::STDOUT_FILENO) } != 1 {
+            return Err(std::io::Error::from_raw_os_error(libc::ENOTTY))
+                .context("setting up the terminal");
+        }
+        let mut original: libc::termios = unsafe { std::mem::zeroed() };
+        if unsafe { libc::tcgetattr(libc::STDIN_FILENO, &mut original) } != 0 {
+            return Err(std::io::Error::last_os_error()).context("setting up the terminal");
+        }
+        let mut raw = original;
+        raw.c_lflag &= !(libc::ICANON | libc::ECHO | libc::ISIG | libc::IEXTEN);
+        raw.c_iflag &= !(libc::IXON | libc::ICRNL);
+        raw.c_cc[libc::VMIN] = 1;
+        raw.c_cc[libc::VTIME] = 0;
+        if unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSAFLUSH, &raw) } != 0 {
+            return Err(std::io::Error::last_os_error()).context("setting up the terminal");
+        }
+        // Interrupt reads when the terminal is resized, to redraw.
+        let handler = resized as extern "C" fn(libc::c_int) as libc::sighandler_t;
+        unsafe {
+            libc::signal(libc::SIGWINCH, handler);
+        }
+        let terminal = Terminal { original };
+        // Switch to the alternate screen and hide the cursor.
+        terminal.write("\x1B[?1049h\x1B[?25l")?;
+        Ok(terminal)
+    }
+
+    fn write(&self, s: &str) -> Result<(), Error> {
+        let mut out = std::io::stdout().lock();
+        out.write_all(s.as_bytes())
+            .and_then(|_| out.flush())
+            .context("writing to stdout")
+    }
+
+    fn draw(&self, content: &str) -> Result<(), Error> {
+        let mut out = String::from("\x1B[H");
+        for (i, line) in content.lines().enumerate() {
+            if i != 0 {
+                out.push('\n');
+            }
+            out.push_str(line);
+            out.push_str("\x1B[K");
+        }
+        out.push_str("\x1B[J");
+        self.write(&out)
+    }
+
+    // Wait for input. Returns no keys if interrupted by a resize.
+    fn read_keys(&self) -> Result<Vec<Key>, Error> {
+        let mut poll = libc::pollfd {
+            fd: libc::STDIN_FILENO,
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        if unsafe { libc::poll(&mut poll, 1, -1) } < 0 {
+            let error = std::io::Error::last_os_error();
+            if error.kind() == std::io::ErrorKind::Interrupted {
+                return Ok(vec![]);
+            }
+            return Err(error).context("reading from the terminal");
+        }
+        let mut buf = [0u8; 1024];
+        let n = unsafe { libc::read(libc::STDIN_FILENO, buf.as_mut_ptr().cast(), buf.len()) };
+        match n {
+            n if n < 0 => Err(std::io::Error::last_os_error()).context("reading from the terminal"),
+            0 => Ok(vec![Key::Ctrl('d')]),
+            n => Ok(parse_keys(&String::from_utf8_lossy(&buf[..n as usize]))),
+        }
+    }
+}
+
+impl Drop for Terminal {
+    fn drop(&mut self) {
+        // Show the cursor and leave the alternate screen.
+        let _ = self.write("\x1B[?25h\x1B[?1049l");
+        unsafe {
+            libc::tcsetattr(libc::STDIN_FILENO, libc::TCSAFLUSH, &self.original);
+            libc::signal(libc::SIGWINCH, libc::SIG_DFL);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn keys() {
+        assert_eq!(
+            parse_keys("\x1B[A\x1B[6~j\x1BOH\x1B\r\x7F\x03é"),
+            vec![
+                Key::Up,
+                Key::PageDown,
+                Key::Char('j'),
+                Key::Home,
+                Key::Escape,
+                Key::Enter,
+                Key::Backspace,
+                Key::Ctrl('c'),
+                Key::Char('é'),
+            ]
+        );
+    }
+
+    #[test]
+    fn filtering() {
+        let items = ["Bill Evans", "Miles Davis", "Milt Jackson"]
+            .iter()
+            .map(|a| Item::Tag(a.to_string()))
+            .collect();
+        let mut level = Level::new("Library".into(), vec![], items);
+        level.move_cursor(2);
+        level.filter = "mil".into();
+        level.apply_filter();
+        assert_eq!(level.visible, vec![1, 2]);
+        assert_eq!(level.current(), Some(2));
+        level.marked.insert(1);
+        assert_eq!(level.targets(), vec![1]);
+    }
+
+    #[test]
+    fn scrolling() {
+        let items = (0..10).map(|i| Item::Tag(i.to_string())).collect();
+        let mut level = Level::new("Library".into(), vec![], items);
+        level.move_cursor(5);
+        assert_eq!(level.scroll(3), 3);
+        // Moving up within the rows shown keeps the view.
+        level.move_cursor(-1);
+        assert_eq!(level.scroll(3), 3);
+        level.move_cursor(-2);
+        assert_eq!(level.scroll(3), 2);
+    }
+
+    #[test]
+    fn narrow_terminal() {
+        let levels = ["Library", "Miles Davis", "Kind of Blue"]
+            .iter()
+            .map(|title| Level::new(title.to_string(), vec![], vec![]))
+            .collect();
+        let mut browser = Browser {
+            hierarchy: Hierarchy::Directories,
+            levels,
+            input: false,
+            message: None,
+        };
+        let screen = browser.render(5, 20);
+        assert!(screen.contains("Library › Miles Dav…"));
+        assert_eq!(screen.lines().last(), Some("a add  i insert  p …"));
+    }
+}
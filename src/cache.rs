@@ -0,0 +1,222 @@
+use crate::cli::{SearchOptions, SearchQuery, Window};
+use crate::error::{Error, WithContext};
+use crate::filter::Filter;
+use crate::raw;
+use crate::sort;
+use crate::tags::Tags;
+use mpdrs::lsinfo::LsInfoResponse;
+use mpdrs::Song;
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::PathBuf;
+
+// A copy of the response to listallinfo, stored on disk for every host, with
+// the time of the database update it was read after. The file starts with
+// that time, followed by the response as received from MPD:
+//
+//     db_update: 1600000000
+//     directory: Miles Davis
+//     file: Miles Davis/Kind of Blue/01 So What.flac
+//     Title: So What
+pub struct Cache {
+    db_update: u64,
+    // The pairs of every directory, playlist and song.
+    groups: Vec<Vec<(String, String)>>,
+}
+
+const ENTRY_KEYS: &[&str] = &["file", "directory", "playlist"];
+
+// The cache of host, refreshed first if the database of MPD has been updated
+// since it was read.
+pub fn load(
+    client: &mut mpdrs::Client,
+    raw: &mut raw::Connection,
+    host: &str,
+) -> Result<Cache, Error> {
+    let db_update = client.stats()?.db_update.as_secs();
+    let cache = match read(host)? {
+        Some(cache) if cache.db_update == db_update => return Ok(cache),
+        Some(cache) => refresh(raw, cache, db_update)?,
+        None => fetch(raw, db_update)?,
+    };
+    write(host, &cache)?;
+    Ok(cache)
+}
+
+// The cache of host as last stored, without connecting to MPD.
+pub fn read(host: &str) -> Result<Option<Cache>, Error> {
+    let contents = match fs::read_to_string(path(host)) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e).context("reading the cache"),
+    };
+    let mut lines = contents.lines();
+    let Some(db_update) = lines
+        .next()
+        .and_then(|l| l.strip_prefix("db_update: "))
+        .and_then(|t| t.parse().ok())
+    else {
+        log::trace!("Ignoring cache without a db_update line.");
+        return Ok(None);
+    };
+    let pairs = lines
+        .filter_map(|line| line.split_once(": "))
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .collect();
+    Ok(Some(Cache {
+        db_update,
+        groups: raw::split(pairs, ENTRY_KEYS),
+    }))
+}
+
+fn write(host: &str, cache: &Cache) -> Result<(), Error> {
+    let path = path(host);
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).context("creating the cache directory")?;
+    }
+    let mut contents = format!("db_update: {}\n", cache.db_update);
+    for (k, v) in cache.groups.iter().flatten() {
+        contents.push_str(&format!("{}: {}\n", k, v));
+    }
+    // Write to a temporary file first, so that a concurrent reader never
+    // sees a partial cache.
+    // The host may contain dots, as an IP address does, so the extension
+    // is appended rather than replaced.
+    let mut temporary = path.clone().into_os_string();
+    temporary.push(".tmp");
+    fs::write(&temporary, contents).context("writing the cache")?;
+    fs::rename(&temporary, &path).context("writing the cache")
+}
+
+// $XDG_CACHE_HOME/davis/<host>, or ~/.cache/davis/<host>.
+fn path(host: &str) -> PathBuf {
+    let dir = match env::var("XDG_CACHE_HOME") {
+        Ok(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => {
+            let home = env::var("HOME").expect("$HOME was not set!");
+            [&*home, ".cache"].iter().collect()
+        }
+    };
+    dir.join("davis").join(host.replace('/', "_"))
+}
+
+fn fetch(raw: &mut raw::Connection, db_update: u64) -> Result<Cache, Error> {
+    log::trace!("Reading the whole database into the cache.");
+    Ok(Cache {
+        db_update,
+        groups: raw::split(raw.command("listallinfo", &[])?, ENTRY_KEYS),
+    })
+}
+
+// Update the cache with the songs modified since it was read, and drop
+// those which no longer exist. Songs which were added without being
+// modified, such as files copied with their modification time, are not
+// found that way, and the whole database is read again instead.
+fn refresh(raw: &mut raw::Connection, cache: Cache, db_update: u64) -> Result<Cache, Error> {
+    let filter = format!("(modified-since \"{}\")", cache.db_update);
+    let mut modified = raw::split(raw.command("find", &[&filter])?, &["file"])
+        .into_iter()
+        .map(|group| (group[0].1.clone(), group))
+        .collect::<HashMap<_, _>>();
+    let count = modified.len();
+    let mut cached = cache
+        .groups
+        .into_iter()
+        .map(|group| (group[0].clone(), group))
+        .collect::<HashMap<_, _>>();
+    let mut groups = vec![];
+    for (kind, path) in raw.command("listall", &[])? {
+        let group = match &*kind {
+            "file" => modified.remove(&path),
+            _ => None,
+        };
+        match group.or_else(|| cached.remove(&(kind.clone(), path.clone()))) {
+            Some(group) => groups.push(group),
+            None if kind == "file" => {
+                log::trace!("{} is missing from the cache.", path);
+                return fetch(raw, db_update);
+            }
+            // New directories and playlists are only missing their
+            // modification time.
+            None => groups.push(vec![(kind, path)]),
+        }
+    }
+    log::trace!("Refreshed the cache with {} modified songs.", count);
+    Ok(Cache { db_update, groups })
+}
+
+impl Cache {
+    // Directories, playlists and songs, in the order of listallinfo.
+    pub fn entries(&self) -> Vec<LsInfoResponse> {
+        self.groups.iter().cloned().map(raw::entry).collect()
+    }
+
+    pub fn songs(&self) -> Vec<Song> {
+        self.entries()
+            .into_iter()
+            .filter_map(|entry| match entry {
+                LsInfoResponse::Song(song) => Some(song),
+                _ => None,
+            })
+            .collect()
+    }
+
+    // The songs matching query, compared like find if exact is set, and
+    // like search otherwise, sorted and windowed as MPD would.
+    pub fn search(
+        &self,
+        query: &SearchQuery,
+        exact: bool,
+        options: &SearchOptions,
+    ) -> Result<Vec<Song>, Error> {
+        let filter = match query {
+            SearchQuery::Expression(expression) => {
+                Filter::parse(expression, !exact).map_err(|e| Error::Filter(e.to_string()))?
+            }
+            SearchQuery::Filters(pairs) => Filter::pairs(pairs, !exact),
+        };
+        let mut songs = self
+            .songs()
+            .into_iter()
+            .filter(|song| filter.matches(song))
+            .collect::<Vec<_>>();
+        match &options.sort {
+            Some(tag) => {
+                let (tag, descending) = match tag.strip_prefix('-') {
+                    Some(tag) => (tag, true),
+                    None => (&**tag, false),
+                };
+                songs.sort_by_cached_key(|song| Tags::from_song(song).get_option_joined(tag));
+                if descending {
+                    songs.reverse();
+                }
+            }
+            None => sort::natural(&mut songs),
+        }
+        if let Some(Window { start, end }) = options.window {
+            let end = end.map_or(songs.len(), |e| (e as usize).min(songs.len()));
+            songs = songs.drain((start as usize).min(end)..end).collect();
+        }
+        Ok(songs)
+    }
+
+    // The sorted values of tag of the songs matching query, like list.
+    pub fn list(&self, tag: &str, query: &SearchQuery) -> Result<Vec<String>, Error> {
+        let songs = self.search(query, true, &SearchOptions::default())?;
+        let mut values = songs
+            .iter()
+            .flat_map(|song| {
+                Tags::from_song(song)
+                    .get(tag)
+                    .into_iter()
+                    .map(str::to_string)
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+        values.sort_unstable();
+        values.dedup();
+        Ok(values)
+    }
+}
@@ -1,5 +1,16 @@
+use crate::album;
+use crate::autodj;
+use crate::bar;
+use crate::browse;
+use crate::fuzzy;
 use crate::logger;
+use crate::ls;
+use crate::now_playing;
+use crate::playlist_file;
+use crate::query;
+use crate::records::{self, Records};
 use crate::seek;
+use crate::sticker;
 use crate::subcommands::find_subcommand;
 use lexopt::prelude::*;
 use std::env;
@@ -12,12 +23,15 @@ pub fn parse_args() -> Result<Opts, lexopt::Error> {
     logger::Logger(opts.verbose).init();
     if opts.subcommand.is_none() {
         log::trace!("No subcommand specified, defaulting to current.");
-        opts.subcommand = Some(SubCommand::Current);
+        opts.subcommand = Some(SubCommand::Current {
+            format: None,
+            follow: Follow::default(),
+        });
     }
 
     if let Some(SubCommand::Custom(v)) = &opts.subcommand {
         let mut v = v.clone();
-        if let Some(subcommand) = find_subcommand(&*v[0]) {
+        if let Some(subcommand) = find_subcommand(&v[0]) {
             log::trace!("Found custom subcommand {:?}", subcommand);
             v[0] = subcommand.as_os_str().to_owned();
             opts.subcommand = Some(SubCommand::Custom(v));
@@ -44,6 +58,7 @@ fn lexopt_parse_args() -> Result<Opts, lexopt::Error> {
     let mut host = None;
     let mut verbose = false;
     let mut plain_formatting = false;
+    let mut json = false;
 
     let mut parser = lexopt::Parser::from_env();
     let mut subcommand = None;
@@ -66,6 +81,9 @@ fn lexopt_parse_args() -> Result<Opts, lexopt::Error> {
             Short('p') | Long("plain") => {
                 plain_formatting = true;
             }
+            Long("json") => {
+                json = true;
+            }
             Value(cmd) => {
                 subcommand = Some(parse_subcommand(cmd, &mut parser)?);
             }
@@ -77,6 +95,7 @@ fn lexopt_parse_args() -> Result<Opts, lexopt::Error> {
         host,
         verbose,
         plain_formatting,
+        json,
         subcommand,
     })
 }
@@ -87,7 +106,41 @@ fn parse_subcommand(
 ) -> Result<SubCommand, lexopt::Error> {
     let cmd = cmd.into_string()?;
     Ok(match &*cmd {
-        "current" => SubCommand::Current,
+        "current" => {
+            let mut format = None;
+            let mut follow = Follow::default();
+            while let Some(arg) = parser.next()? {
+                match arg {
+                    Short('f') | Long("format") => format = Some(parser.value()?.into_string()?),
+                    Short('F') | Long("follow") => follow.enabled = true,
+                    Long("title") => follow.title = true,
+                    _ => return Err(arg.unexpected()),
+                }
+            }
+            SubCommand::Current { format, follow }
+        }
+        "info" => {
+            let mut targets = vec![];
+            let mut format = None;
+            while let Some(arg) = parser.next()? {
+                match arg {
+                    Short('f') | Long("format") => format = Some(parser.value()?.into_string()?),
+                    Long("next") => targets.push(now_playing::Target::Next),
+                    Value(v) => {
+                        let v = v.into_string()?;
+                        targets.push(match v.parse() {
+                            Ok(position) => now_playing::Target::Position(position),
+                            Err(_) => now_playing::Target::Path(v),
+                        });
+                    }
+                    _ => return Err(arg.unexpected()),
+                }
+            }
+            if targets.is_empty() {
+                return Err("missing argument: path, queue position or --next".into());
+            }
+            SubCommand::Info { targets, format }
+        }
         "play" => SubCommand::Play {
             position: if let Some(Value(i)) = parser.next()? {
                 Some(i.parse()?)
@@ -97,54 +150,394 @@ fn parse_subcommand(
         },
         "pause" => SubCommand::Pause,
         "toggle" => SubCommand::Toggle,
-        "ls" => SubCommand::Ls {
-            path: if let Some(Value(i)) = parser.next()? {
-                Some(i.parse()?)
-            } else {
-                None
-            },
-        },
+        "ls" => {
+            let mut path = None;
+            let mut records = Records::default();
+            let mut options = ls::Options::default();
+            while let Some(arg) = parser.next()? {
+                match arg {
+                    Short('R') | Long("recursive") => options.recursive = true,
+                    Short('l') | Long("long") => options.long = true,
+                    Short('F') | Long("classify") => options.classify = true,
+                    Long("sort") => options.sort = Some(parser.value()?.parse()?),
+                    Short('r') | Long("reverse") => options.reverse = true,
+                    Short('0') | Long("null") => records.null = true,
+                    Long("fields") => {
+                        let fields = parser.value()?.into_string()?;
+                        records.fields = Some(records::parse_fields(&fields));
+                    }
+                    Value(p) if path.is_none() => path = Some(p.into_string()?),
+                    _ => return Err(arg.unexpected()),
+                }
+            }
+            SubCommand::Ls {
+                path,
+                records,
+                options,
+            }
+        }
+        "browse" => {
+            let mut hierarchy = browse::Hierarchy::Tags(vec![
+                "genre".to_string(),
+                "artist".to_string(),
+                "album".to_string(),
+            ]);
+            while let Some(arg) = parser.next()? {
+                match arg {
+                    Long("dirs") => hierarchy = browse::Hierarchy::Directories,
+                    Long("tags") => {
+                        let tags = parser.value()?.into_string()?;
+                        let tags = tags
+                            .split(',')
+                            .filter(|t| !t.is_empty())
+                            .map(str::to_string)
+                            .collect::<Vec<_>>();
+                        if tags.is_empty() {
+                            return Err("--tags requires at least one tag".into());
+                        }
+                        hierarchy = browse::Hierarchy::Tags(tags);
+                    }
+                    _ => return Err(arg.unexpected()),
+                }
+            }
+            SubCommand::Browse { hierarchy }
+        }
         "clear" => SubCommand::Clear,
         "next" => SubCommand::Next,
         "prev" => SubCommand::Prev,
         "stop" => SubCommand::Stop,
-        "add" => SubCommand::Add {
-            path: next_arg("path", parser)?,
-        },
+        "add" => {
+            let mut path = None;
+            let mut null = false;
+            while let Some(arg) = parser.next()? {
+                match arg {
+                    Short('0') | Long("null") => null = true,
+                    Value(p) if path.is_none() => path = Some(p.into_string()?),
+                    _ => return Err(arg.unexpected()),
+                }
+            }
+            SubCommand::Add {
+                path: path.ok_or("missing argument: path")?,
+                null,
+            }
+        }
         "load" => SubCommand::Load {
             path: next_arg("path", parser)?,
         },
-        "queue" => SubCommand::Queue,
-        "search" => {
+        "queue" => {
+            let mut format = None;
+            let mut follow = Follow::default();
+            while let Some(arg) = parser.next()? {
+                match arg {
+                    Short('f') | Long("format") => format = Some(parser.value()?.into_string()?),
+                    Short('F') | Long("follow") => follow.enabled = true,
+                    Long("title") => follow.title = true,
+                    Value(action) => return parse_queue_action(action, parser),
+                    _ => return Err(arg.unexpected()),
+                }
+            }
+            SubCommand::Queue { format, follow }
+        }
+        "search" | "find" => {
             let mut query = vec![];
-            while let Some(Value(i)) = parser.next()? {
-                query.push(i.into_string()?);
+            let mut format = None;
+            let mut records = Records::default();
+            let mut options = SearchOptions::default();
+            let mut fuzzy = false;
+            let mut kind = None;
+            let mut limit = None;
+            let mut add = false;
+            let mut cached = false;
+            let mut rating = None;
+            while let Some(arg) = next_query_arg(parser)? {
+                match arg {
+                    Short('f') | Long("format") => format = Some(parser.value()?.into_string()?),
+                    Long("fuzzy") => fuzzy = true,
+                    Long("rating") => rating = Some(parse_rating(&parser.value()?.into_string()?)?),
+                    Long("by") => kind = Some(parser.value()?.parse()?),
+                    Short('n') | Long("limit") => limit = Some(parser.value()?.parse()?),
+                    Long("add") => add = true,
+                    Long("cached") => cached = true,
+                    Long("sort") => options.sort = Some(parser.value()?.into_string()?),
+                    Long("window") => options.window = Some(parser.value()?.parse()?),
+                    Short('0') | Long("null") => records.null = true,
+                    Long("fields") => {
+                        let fields = parser.value()?.into_string()?;
+                        records.fields = Some(records::parse_fields(&fields));
+                    }
+                    Value(i) => query.push(i.into_string()?),
+                    Short(c) => query.push(negated_term(c)),
+                    _ => return Err(arg.unexpected()),
+                }
             }
-            if query.len() == 1 || query.len() % 2 == 0 {
-                SubCommand::Search {
-                    query: SearchQuery::from_strings(query)?
+            if fuzzy {
+                if query.is_empty() {
+                    return Err("missing argument: text".into());
+                }
+                if options.sort.is_some() || options.window.is_some() {
+                    return Err("--sort and --window can not be combined with --fuzzy".into());
+                }
+                if rating.is_some() {
+                    return Err("--rating can not be combined with --fuzzy".into());
+                }
+                SubCommand::FuzzySearch {
+                    text: query.join(" "),
+                    kind: kind.unwrap_or(fuzzy::Kind::Songs),
+                    limit,
+                    add,
+                    format,
+                    records,
                 }
             } else {
-                return Err("Search argument must be either a single string, or pairs of key-value strings.".into())
+                // The window would be applied before the songs are filtered by
+                // rating.
+                if rating.is_some() && options.window.is_some() {
+                    return Err("--window can not be combined with --rating".into());
+                }
+                SubCommand::Search {
+                    query: SearchQuery::from_strings(query)?,
+                    exact: cmd == "find",
+                    cached,
+                    rating,
+                    add,
+                    format,
+                    records,
+                    options,
+                }
             }
         }
         "list" => {
-            let tag = next_arg("tag", parser)?;
+            let mut tag = None;
             let mut query = vec![];
-            while let Some(Value(i)) = parser.next()? {
-                query.push(i.into_string()?);
+            let mut null = false;
+            let mut cached = false;
+            while let Some(arg) = next_query_arg(parser)? {
+                match arg {
+                    Short('0') | Long("null") => null = true,
+                    Long("cached") => cached = true,
+                    // Negated terms may come before the tag.
+                    Value(t) if tag.is_none() && !t.to_string_lossy().starts_with('-') => {
+                        tag = Some(t.into_string()?)
+                    }
+                    Value(i) => query.push(i.into_string()?),
+                    Short(c) => query.push(negated_term(c)),
+                    _ => return Err(arg.unexpected()),
+                }
             }
 
             SubCommand::List {
-                tag,
-                query: SearchQuery::from_strings(query)?
+                tag: tag.ok_or("missing argument: tag")?,
+                query: SearchQuery::from_strings(query)?,
+                null,
+                cached,
+            }
+        }
+        "playlist" => match &*next_arg::<String>("action", parser)? {
+            "show" => {
+                let mut name = None;
+                let mut format = None;
+                while let Some(arg) = parser.next()? {
+                    match arg {
+                        Short('f') | Long("format") => {
+                            format = Some(parser.value()?.into_string()?);
+                        }
+                        Value(n) if name.is_none() => name = Some(n.into_string()?),
+                        _ => return Err(arg.unexpected()),
+                    }
+                }
+                SubCommand::PlaylistShow {
+                    name: name.ok_or("missing argument: name")?,
+                    format,
+                }
+            }
+            action => return Err(format!("unknown playlist action: {}", action).into()),
+        },
+        "smart" => match &*next_arg::<String>("action", parser)? {
+            "list" => SubCommand::SmartList,
+            "show" => {
+                let mut name = None;
+                let mut format = None;
+                while let Some(arg) = parser.next()? {
+                    match arg {
+                        Short('f') | Long("format") => {
+                            format = Some(parser.value()?.into_string()?);
+                        }
+                        Value(n) if name.is_none() => name = Some(n.into_string()?),
+                        _ => return Err(arg.unexpected()),
+                    }
+                }
+                SubCommand::SmartShow {
+                    name: name.ok_or("missing argument: name")?,
+                    format,
+                }
+            }
+            "load" => SubCommand::SmartLoad {
+                name: next_arg("name", parser)?,
+            },
+            "save" => SubCommand::SmartSave {
+                name: next_arg("name", parser)?,
+            },
+            action => return Err(format!("unknown smart action: {}", action).into()),
+        },
+        "bar" => {
+            let mut style = None;
+            while let Some(arg) = parser.next()? {
+                match arg {
+                    Short('s') | Long("style") => style = Some(parser.value()?.parse()?),
+                    _ => return Err(arg.unexpected()),
+                }
+            }
+            SubCommand::Bar {
+                style: style.ok_or("missing style option")?,
             }
         }
         "read-comments" => SubCommand::ReadComments {
             file: next_arg("file", parser)?,
         },
-        "update" => SubCommand::Update,
-        "status" => SubCommand::Status,
+        "update" | "rescan" => {
+            let mut path = None;
+            let mut wait = false;
+            while let Some(arg) = parser.next()? {
+                match arg {
+                    Short('w') | Long("wait") => wait = true,
+                    Value(p) if path.is_none() => path = Some(p.into_string()?),
+                    _ => return Err(arg.unexpected()),
+                }
+            }
+            SubCommand::Update {
+                path,
+                rescan: cmd == "rescan",
+                wait,
+            }
+        }
+        "album" | "work" => {
+            let mut mode = album::Mode::List;
+            while let Some(arg) = parser.next()? {
+                let next = match arg {
+                    Long("add") => album::Mode::Add,
+                    Long("replace") => album::Mode::Replace,
+                    Long("play-from-here") => album::Mode::PlayFromHere,
+                    _ => return Err(arg.unexpected()),
+                };
+                if !matches!(mode, album::Mode::List) {
+                    return Err(
+                        "only one of --add, --replace and --play-from-here can be given".into(),
+                    );
+                }
+                mode = next;
+            }
+            SubCommand::Album {
+                group: if cmd == "work" {
+                    album::Group::Work
+                } else {
+                    album::Group::Album
+                },
+                mode,
+            }
+        }
+        "sticker" => SubCommand::Sticker {
+            action: parse_sticker_action(parser)?,
+        },
+        "rate" => SubCommand::Rate {
+            rating: parse_rating(&next_arg::<String>("rating", parser)?)?,
+        },
+        "autodj" => {
+            let mut query = vec![];
+            let mut options = autodj::Options::default();
+            while let Some(arg) = next_query_arg(parser)? {
+                match arg {
+                    Short('n') | Long("upcoming") => options.upcoming = parser.value()?.parse()?,
+                    Long("history") => options.history = parser.value()?.parse()?,
+                    Long("albums") => options.albums = true,
+                    Long("remove") => options.keep = Some(options.keep.unwrap_or(0)),
+                    Long("keep") => options.keep = Some(parser.value()?.parse()?),
+                    Value(i) => query.push(i.into_string()?),
+                    Short(c) => query.push(negated_term(c)),
+                    _ => return Err(arg.unexpected()),
+                }
+            }
+            SubCommand::Autodj {
+                query: SearchQuery::from_strings(query)?,
+                options,
+            }
+        }
+        command @ ("random-album" | "random-track") => {
+            let albums = command == "random-album";
+            let mut query = vec![];
+            let mut n = 1;
+            let mut print = false;
+            let mut play = false;
+            let mut format = None;
+            while let Some(arg) = next_query_arg(parser)? {
+                match arg {
+                    Short('n') if !albums => n = parser.value()?.parse()?,
+                    Long("print") => print = true,
+                    Long("play") => play = true,
+                    Short('f') | Long("format") => {
+                        format = Some(parser.value()?.into_string()?);
+                    }
+                    Value(i) => query.push(i.into_string()?),
+                    Short(c) => query.push(negated_term(c)),
+                    _ => return Err(arg.unexpected()),
+                }
+            }
+            if print && play {
+                return Err("--play can not be combined with --print".into());
+            }
+            SubCommand::Random {
+                query: SearchQuery::from_strings(query)?,
+                albums,
+                n,
+                print,
+                play,
+                format,
+            }
+        }
+        "related" => {
+            let mut add = None;
+            while let Some(arg) = parser.next()? {
+                match arg {
+                    Short('a') | Long("add") => add = Some(parser.value()?.parse()?),
+                    _ => return Err(arg.unexpected()),
+                }
+            }
+            SubCommand::Related { add }
+        }
+        "stats" => SubCommand::Stats,
+        "count" => {
+            let mut query = vec![];
+            while let Some(arg) = next_query_arg(parser)? {
+                match arg {
+                    Value(i) => query.push(i.into_string()?),
+                    Short(c) => query.push(negated_term(c)),
+                    _ => return Err(arg.unexpected()),
+                }
+            }
+            let group = match query.len() {
+                n if n >= 2 && query[n - 2] == "group" => {
+                    let group = query.pop();
+                    query.pop();
+                    group
+                }
+                _ => None,
+            };
+            SubCommand::Count {
+                query: SearchQuery::from_strings(query)?,
+                group,
+            }
+        }
+        "hosts" => SubCommand::Hosts,
+        "status" => {
+            let mut follow = Follow::default();
+            while let Some(arg) = parser.next()? {
+                match arg {
+                    Short('F') | Long("follow") => follow.enabled = true,
+                    Long("title") => follow.title = true,
+                    _ => return Err(arg.unexpected()),
+                }
+            }
+            SubCommand::Status { follow }
+        }
         "albumart" => {
             let mut output = None;
             let mut song_path = None;
@@ -162,6 +555,16 @@ fn parse_subcommand(
                 song_path,
             }
         }
+        "album-info" => {
+            let mut song_path = None;
+            while let Some(arg) = parser.next()? {
+                match arg {
+                    Value(path) if song_path.is_none() => song_path = Some(path.into_string()?),
+                    _ => return Err(arg.unexpected()),
+                }
+            }
+            SubCommand::AlbumInfo { song_path }
+        }
         "mv" => SubCommand::Mv {
             from: next_arg("from", parser)?,
             to: next_arg("to", parser)?,
@@ -172,9 +575,21 @@ fn parse_subcommand(
         "seek" => SubCommand::Seek {
             position: next_arg("position", parser)?,
         },
-        "tab" => SubCommand::Tab {
-            path: next_arg("path", parser).unwrap_or_else(|_| "".into()),
-        },
+        "tab" => {
+            let mut path = None;
+            let mut null = false;
+            while let Some(arg) = parser.next()? {
+                match arg {
+                    Short('0') | Long("null") => null = true,
+                    Value(p) if path.is_none() => path = Some(p.into_string()?),
+                    _ => return Err(arg.unexpected()),
+                }
+            }
+            SubCommand::Tab {
+                path: path.unwrap_or_default(),
+                null,
+            }
+        }
         cmd => {
             let mut remaining = vec![];
             while let Some(Value(i)) = parser.next()? {
@@ -187,15 +602,103 @@ fn parse_subcommand(
     })
 }
 
+fn parse_queue_action(
+    action: OsString,
+    parser: &mut lexopt::Parser,
+) -> Result<SubCommand, lexopt::Error> {
+    Ok(match &*action.into_string()? {
+        "export" => {
+            let mut format = None;
+            let mut file = None;
+            while let Some(arg) = parser.next()? {
+                match arg {
+                    Short('f') | Long("format") => {
+                        format = Some(parser.value()?.parse()?);
+                    }
+                    Value(path) => file = Some(path.into_string()?),
+                    _ => return Err(arg.unexpected()),
+                }
+            }
+            SubCommand::QueueExport { format, file }
+        }
+        "import" => SubCommand::QueueImport {
+            file: next_arg("file", parser)?,
+        },
+        action => return Err(format!("unknown queue action: {}", action).into()),
+    })
+}
+
+fn parse_sticker_action(parser: &mut lexopt::Parser) -> Result<sticker::Action, lexopt::Error> {
+    Ok(match &*next_arg::<String>("action", parser)? {
+        "get" => sticker::Action::Get {
+            path: next_arg("path", parser)?,
+            name: next_arg("name", parser)?,
+        },
+        "set" => sticker::Action::Set {
+            path: next_arg("path", parser)?,
+            name: next_arg("name", parser)?,
+            value: next_arg("value", parser)?,
+        },
+        "delete" => sticker::Action::Delete {
+            path: next_arg("path", parser)?,
+            name: match parser.next()? {
+                Some(Value(name)) => Some(name.into_string()?),
+                Some(arg) => return Err(arg.unexpected()),
+                None => None,
+            },
+        },
+        "list" => sticker::Action::List {
+            path: next_arg("path", parser)?,
+        },
+        "find" => {
+            let mut name = None;
+            let mut path = None;
+            let mut value = None;
+            while let Some(arg) = parser.next()? {
+                match arg {
+                    Long("value") => value = Some(parser.value()?.into_string()?),
+                    Value(n) if name.is_none() => name = Some(n.into_string()?),
+                    Value(p) if path.is_none() => path = Some(p.into_string()?),
+                    _ => return Err(arg.unexpected()),
+                }
+            }
+            sticker::Action::Find {
+                name: name.ok_or("missing argument: name")?,
+                path: path.unwrap_or_default(),
+                value,
+            }
+        }
+        "count-plays" => sticker::Action::CountPlays,
+        action => return Err(format!("unknown sticker action: {}", action).into()),
+    })
+}
+
+// A rating from 0 to 5.
+fn parse_rating(s: &str) -> Result<u8, lexopt::Error> {
+    match s.parse() {
+        Ok(rating) if rating <= 5 => Ok(rating),
+        _ => Err(format!("invalid rating, expected 0 to 5: {}", s).into()),
+    }
+}
+
 pub struct Opts {
     pub host: Option<String>,
     pub verbose: bool,
     pub plain_formatting: bool,
+    pub json: bool,
     pub subcommand: Option<SubCommand>,
 }
 
 pub enum SubCommand {
-    Current,
+    Current {
+        format: Option<String>,
+        follow: Follow,
+    },
+    // The view of current for other songs.
+    Info {
+        targets: Vec<now_playing::Target>,
+        format: Option<String>,
+    },
     Play {
         position: Option<NonZeroU32>,
     },
@@ -203,6 +706,11 @@ pub enum SubCommand {
     Toggle,
     Ls {
         path: Option<String>,
+        records: Records,
+        options: ls::Options,
+    },
+    Browse {
+        hierarchy: browse::Hierarchy,
     },
     Clear,
     Next,
@@ -210,27 +718,115 @@ pub enum SubCommand {
     Stop,
     Add {
         path: String,
+        null: bool,
     },
     Load {
         path: String,
     },
-    Queue,
+    Queue {
+        format: Option<String>,
+        follow: Follow,
+    },
+    QueueExport {
+        format: Option<playlist_file::Format>,
+        file: Option<String>,
+    },
+    QueueImport {
+        file: String,
+    },
     Search {
         query: SearchQuery,
+        exact: bool,
+        cached: bool,
+        rating: Option<u8>,
+        add: bool,
+        format: Option<String>,
+        records: Records,
+        options: SearchOptions,
+    },
+    FuzzySearch {
+        text: String,
+        kind: fuzzy::Kind,
+        limit: Option<usize>,
+        add: bool,
+        format: Option<String>,
+        records: Records,
     },
     List {
         tag: String,
         query: SearchQuery,
+        null: bool,
+        cached: bool,
+    },
+    PlaylistShow {
+        name: String,
+        format: Option<String>,
+    },
+    SmartList,
+    SmartShow {
+        name: String,
+        format: Option<String>,
+    },
+    SmartLoad {
+        name: String,
+    },
+    SmartSave {
+        name: String,
     },
     ReadComments {
         file: String,
     },
-    Update,
-    Status,
+    Bar {
+        style: bar::Style,
+    },
+    Update {
+        path: Option<String>,
+        rescan: bool,
+        wait: bool,
+    },
+    Album {
+        group: album::Group,
+        mode: album::Mode,
+    },
+    Sticker {
+        action: sticker::Action,
+    },
+    Rate {
+        rating: u8,
+    },
+    Autodj {
+        query: SearchQuery,
+        options: autodj::Options,
+    },
+    // A random album, or n random songs, added to the queue or printed.
+    Random {
+        query: SearchQuery,
+        albums: bool,
+        n: usize,
+        print: bool,
+        play: bool,
+        format: Option<String>,
+    },
+    // List the music related to the current song, or add the nth of it.
+    Related {
+        add: Option<usize>,
+    },
+    Stats,
+    Count {
+        query: SearchQuery,
+        group: Option<String>,
+    },
+    Hosts,
+    Status {
+        follow: Follow,
+    },
     Albumart {
         song_path: Option<String>,
         output: String,
     },
+    AlbumInfo {
+        song_path: Option<String>,
+    },
     Mv {
         from: NonZeroU32,
         to: NonZeroUsize,
@@ -243,17 +839,73 @@ pub enum SubCommand {
     },
     Tab {
         path: String,
+        null: bool,
     },
     Custom(Vec<OsString>),
 }
 
+// Options for redrawing a view on changes.
+#[derive(Default, Clone, Copy)]
+pub struct Follow {
+    pub enabled: bool,
+    pub title: bool,
+}
+
+// Sorting and window options for search and find, which are applied by MPD.
+#[derive(Default)]
+pub struct SearchOptions {
+    pub sort: Option<String>,
+    pub window: Option<Window>,
+}
+
+impl SearchOptions {
+    pub fn to_args(&self) -> Vec<String> {
+        let mut args = vec![];
+        if let Some(sort) = &self.sort {
+            args.extend(["sort".to_string(), sort.clone()]);
+        }
+        if let Some(Window { start, end }) = self.window {
+            let end = end.map_or_else(String::new, |e| e.to_string());
+            args.extend(["window".to_string(), format!("{}:{}", start, end)]);
+        }
+        args
+    }
+}
+
+// A range of results, from start up to but not including end.
+#[derive(Clone, Copy)]
+pub struct Window {
+    pub start: u32,
+    pub end: Option<u32>,
+}
+
+impl FromStr for Window {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("invalid window, expected start:end: {}", s);
+        let (start, end) = s.split_once(':').ok_or_else(invalid)?;
+        let window = Window {
+            start: start.parse().map_err(|_| invalid())?,
+            end: match end {
+                "" => None,
+                end => Some(end.parse().map_err(|_| invalid())?),
+            },
+        };
+        match window.end {
+            Some(end) if end < window.start => Err(invalid()),
+            _ => Ok(window),
+        }
+    }
+}
+
 pub enum SearchQuery {
     Expression(String),
     Filters(Vec<(String, String)>)
 }
 
 impl SearchQuery {
-    pub fn to_mpd_query(&self) -> mpdrs::Query {
+    pub fn to_mpd_query(&self) -> mpdrs::Query<'_> {
         match self {
             SearchQuery::Expression(query) => {
                 mpdrs::Query::Expression(query.clone())
@@ -262,26 +914,78 @@ impl SearchQuery {
             SearchQuery::Filters(filters) => {
                 let mut query = mpdrs::FilterQuery::new();
                 for pair in filters {
-                    query.and(mpdrs::Term::Tag(&*pair.0), &*pair.1);
+                    query.and(mpdrs::Term::Tag(&pair.0), &pair.1);
                 }
                 mpdrs::Query::Filters(query)
             }
         }
     }
 
+    // The query as arguments to a MPD command.
+    pub fn to_args(&self) -> Vec<&str> {
+        match self {
+            SearchQuery::Expression(query) => vec![query],
+            SearchQuery::Filters(filters) => filters
+                .iter()
+                .flat_map(|(tag, value)| [&**tag, &**value])
+                .collect(),
+        }
+    }
+
+    // A query written as a single string, as in the configuration file.
+    pub fn from_text(text: &str) -> Result<SearchQuery, String> {
+        if query::is_expression(text) {
+            Ok(SearchQuery::Expression(text.to_string()))
+        } else {
+            let expression = query::compile(&query::split(text)).map_err(|e| e.to_string())?;
+            Ok(SearchQuery::Expression(expression))
+        }
+    }
+
+    // A single argument in the MPD filter syntax, pairs of tags and values,
+    // or a query in the syntax of the query module. Any word can be the tag
+    // of a pair, since MPD may know tags and filters which davis does not,
+    // such as AudioFormat or modified-since.
     pub fn from_strings(mut strings: Vec<String>) -> Result<SearchQuery, lexopt::Error> {
-        if strings.len() == 1 {
+        let is_key_char = |c: char| c.is_alphanumeric() || c == '_' || c == '-';
+        let is_key = |s: &String| s.starts_with(char::is_alphabetic) && s.chars().all(is_key_char);
+        if strings.len() == 1 && query::is_expression(&strings[0]) {
             Ok(SearchQuery::Expression(strings.remove(0)))
-        } else if strings.len() % 2 == 0 {
+        } else if strings.len().is_multiple_of(2) && strings.iter().step_by(2).all(is_key) {
             let mut filters = Vec::new();
             for pair in strings.chunks(2) {
                 filters.push((pair[0].clone(), pair[1].clone()));
             }
             Ok(SearchQuery::Filters(filters))
         } else {
-            Err("Search argument must be either a single string, or pairs of key-value strings.".into())
+            let expression = query::compile(&strings).map_err(|e| e.to_string())?;
+            Ok(SearchQuery::Expression(expression))
+        }
+    }
+}
+
+// The next argument of a subcommand taking a query. A word starting with a
+// single dash is a negated term, such as -name:foo or -funk, unless it is
+// just a short option, such as -n. Options therefore take their values as a
+// separate argument in these subcommands.
+fn next_query_arg(parser: &mut lexopt::Parser) -> Result<Option<lexopt::Arg<'_>>, lexopt::Error> {
+    let is_term = |arg: &std::ffi::OsStr| {
+        arg.to_str()
+            .is_some_and(|arg| arg.starts_with('-') && !arg.starts_with("--") && arg.len() > 2)
+    };
+    // raw_args fails in the middle of a group of short options, which can
+    // not be a term.
+    if let Ok(mut args) = parser.raw_args() {
+        if let Some(term) = args.next_if(is_term) {
+            return Ok(Some(Value(term)));
         }
     }
+    parser.next()
+}
+
+// Single letter negated terms, such as -x, are parsed as short options.
+fn negated_term(c: char) -> String {
+    format!("-{}", c)
 }
 
 pub fn print_help() {
@@ -299,39 +1003,208 @@ FLAGS:
         --help     Prints help information.
     -v, --verbose  Enable verbose output.
     -p, --plain    Disable decorations in output, useful for scripting.
+        --json     Print output as JSON, useful for scripting.
 
 OPTIONS:
     -h, --host <host>  IP/hostname or a label defined in the config file.
 
 SUBCOMMANDS:
     davis add <path>                   Add items in path to queue.
+    davis add [-0] -                   Add the paths read from stdin to queue.
+    davis album [--add|--replace]      List or queue the current album.
+    davis album-info [path]            Summarize the album of a song.
     davis albumart -o <output> [path]  Download albumart.
+    davis autodj [-n n] [query]        Keep the queue filled with random songs.
+    davis bar --style <style>          Print now playing for status bars.
+    davis browse [--tags t] [--dirs]   Browse the library interactively.
     davis clear                        Clear the current queue.
-    davis current                      Display the currently playing song.
+    davis current [-f format] [-F]     Display the currently playing song.
+    davis count <query> [group <tag>]  Count songs and playtime per tag.
     davis del <index>                  Remove song at index from queue.
+    davis find [-f format] <query>     Find files exactly matching query.
     davis help                         Prints this message.
-    davis list <tag> [query]           List values for tag filtered by query.
+    davis hosts                        List the hosts defined in the config file.
+    davis info <path|pos|--next>       Display the tags of any song.
+    davis list [-0] <tag> [query]      List values for tag filtered by query.
     davis load <path>                  Load playlist at path to queue.
-    davis ls [path]                    List items in path.
+    davis ls [-0] [--fields f] [path]  List items in path.
+    davis ls [-RlFr] [--sort k] [path] List items as a tree or in detail.
     davis mv <from> <to>               Move song in queue by index.
     davis next                         Skip to next song in queue.
     davis pause                        Pause playback.
     davis play                         Continue playback from current state.
     davis play [index]                 Start playback from index in queue.
+    davis playlist show <name>         Display the songs in a stored playlist.
     davis prev                         Go back to previous song in queue.
-    davis queue                        Display the current queue.
+    davis queue [-f format] [-F]       Display the current queue.
+    davis queue export [-f fmt] [file] Export the queue as m3u, xspf or json.
+    davis queue import <file>          Add songs from a m3u, pls or xspf file.
+    davis random-album [query]         Add a random album to the queue.
+    davis random-track [-n n] [query]  Add random songs to the queue.
+    davis rate <0-5>                   Rate the current song, 0 to unrate it.
     davis read-comments <file>         Read raw metadata tags for file.
-    davis search <query>               Search for files matching query.
+    davis related [--add n]            List music related to the current song.
+    davis rescan [-w] [path]           Update, also reading unmodified files.
+    davis search [-f format] <query>   Search for files matching query.
+    davis search --cached <query>      Search the local copy of the database.
+    davis search --fuzzy <text>        Search by fuzzy matching, best first.
+    davis search --rating <n> <query>  Search for songs rated at least n.
     davis seek <position>              Seek to position.
-    davis status                       Display MPD status.
+    davis smart list                   List the smart playlists of the config.
+    davis smart show [-f fmt] <name>   Display the songs of a smart playlist.
+    davis smart load <name>            Add the songs of a smart playlist.
+    davis smart save <name>            Save a smart playlist as a playlist.
+    davis stats                        Display database statistics.
+    davis status [-F]                  Display MPD status.
+    davis sticker list <path>          List the stickers of a song.
+    davis sticker get <path> <k>       Print the value of a sticker.
+    davis sticker set <path> <k> <v>   Set a sticker of a song.
+    davis sticker delete <path> [k]    Delete one or all stickers of a song.
+    davis sticker find <k> [path]      List the songs with a sticker.
+    davis sticker count-plays          Count plays in the playcount sticker.
     davis stop                         Stop playback.
     davis toggle                       Toggle between play/pause.
-    davis update                       Update the MPD database.
+    davis update [-w] [path]           Update the MPD database.
+    davis work [--add|--replace]       List or queue the current work.
+
+FORMAT:
+    The output of current, queue, search and playlist show can be formatted
+    with a template, or a named template from the config file, such as:
+        davis queue --format '[%artist% - ]%title%|%file%'
+
+FOLLOW:
+    With -F/--follow, current, status and queue redraw their output on every
+    change until interrupted. Add --title to also set the terminal title.
+
+SORTING:
+    search and find sort results by disc and track number within each album.
+    Use --sort <tag>, or --sort -<tag> for descending order, to let MPD sort
+    by a tag instead, and --window <start>:<end> to only print some results.
+
+RECORDS:
+    With -0, ls, search, list and tab terminate records with NUL instead of
+    newline. With --fields, ls and search print tab separated fields, such as:
+        davis search --fields artist,title,file album 'Kind of Blue'
 
 QUERY:
-    A query can either be a single argument in the MPD filter syntax, such as:
+    A query is a list of terms, such as tag:value for equality, tag~value for
+    substrings, tag=~regex, tag>=number or a value matching any tag. Terms
+    can be negated with -, and grouped with parentheses and OR:
+        davis search artist:'Miles Davis' -genre:live '(album~blue OR title~blue)'
+    A query can also be a single argument in the MPD filter syntax, such as:
         davis search '((artist == \"Miles Davis\") AND (album == \"Kind Of Blue\"))'
     Or a list of arguments-pairs, each pair corresponding to a filter, such as:
         davis search artist 'Miles Davis' album 'Kind Of Blue'
     More information on the MPD filter syntax is available at:
         https://mpd.readthedocs.io/en/latest/protocol.html#filters";
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn parse(args: &[&str]) -> SubCommand {
+        let rest = args[1..].iter().map(|a| a.to_string()).collect::<Vec<_>>();
+        let mut parser = lexopt::Parser::from_args(rest);
+        parse_subcommand(args[0].into(), &mut parser).unwrap()
+    }
+
+    fn compiled(terms: &[&str]) -> String {
+        let terms = terms.iter().map(|t| t.to_string()).collect::<Vec<_>>();
+        query::compile(&terms).unwrap()
+    }
+
+    #[test]
+    fn negated_terms() {
+        let terms = ["-name:foo", "-file:x", "-0s", "-funk", "-new"];
+        let mut args = vec!["search", "-0"];
+        args.extend(terms);
+        match parse(&args) {
+            SubCommand::Search {
+                query,
+                format,
+                records,
+                ..
+            } => {
+                assert_eq!(query.to_args(), vec![compiled(&terms)]);
+                assert_eq!(format, None);
+                assert!(records.null);
+            }
+            _ => panic!("expected search"),
+        }
+        match parse(&["list", "-0s", "album", "-0", "-file:x"]) {
+            SubCommand::List {
+                tag, query, null, ..
+            } => {
+                assert_eq!(tag, "album");
+                assert_eq!(query.to_args(), vec![compiled(&["-0s", "-file:x"])]);
+                assert!(null);
+            }
+            _ => panic!("expected list"),
+        }
+        match parse(&["count", "-name:foo", "group", "album"]) {
+            SubCommand::Count { query, group } => {
+                assert_eq!(query.to_args(), vec![compiled(&["-name:foo"])]);
+                assert_eq!(group.as_deref(), Some("album"));
+            }
+            _ => panic!("expected count"),
+        }
+    }
+
+    #[test]
+    fn pairs() {
+        let pairs = ["AudioFormat", "44100:24:2", "artist", "Miles Davis"];
+        let mut args = vec!["search"];
+        args.extend(pairs);
+        match parse(&args) {
+            SubCommand::Search { query, .. } => {
+                assert_eq!(query.to_args(), pairs);
+            }
+            _ => panic!("expected search"),
+        }
+        match parse(&["search", "artist:Miles Davis", "blue"]) {
+            SubCommand::Search { query, .. } => {
+                let terms = ["artist:Miles Davis", "blue"];
+                assert_eq!(query.to_args(), vec![compiled(&terms)]);
+            }
+            _ => panic!("expected search"),
+        }
+    }
+
+    #[test]
+    fn autodj() {
+        match parse(&["autodj", "-n", "3", "-name:foo", "-new", "--albums"]) {
+            SubCommand::Autodj { query, options } => {
+                assert_eq!(query.to_args(), vec![compiled(&["-name:foo", "-new"])]);
+                assert_eq!(options.upcoming, 3);
+                assert!(options.albums);
+            }
+            _ => panic!("expected autodj"),
+        }
+    }
+
+    #[test]
+    fn random() {
+        match parse(&["random-track", "-name:foo", "-n", "2", "-funk", "-file:x"]) {
+            SubCommand::Random {
+                query, n, format, ..
+            } => {
+                let terms = ["-name:foo", "-funk", "-file:x"];
+                assert_eq!(query.to_args(), vec![compiled(&terms)]);
+                assert_eq!(n, 2);
+                assert_eq!(format, None);
+            }
+            _ => panic!("expected random"),
+        }
+    }
+
+    #[test]
+    fn limit() {
+        match parse(&["search", "--fuzzy", "-n", "3", "-name:blue", "-n", "5"]) {
+            SubCommand::FuzzySearch { text, limit, .. } => {
+                assert_eq!(text, "-name:blue");
+                assert_eq!(limit, Some(5));
+            }
+            _ => panic!("expected fuzzy search"),
+        }
+    }
+}
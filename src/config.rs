@@ -1,3 +1,5 @@
+use crate::ansi::Theme;
+use crate::cli::SearchQuery;
 use crate::error::{Error, WithContext};
 use configparser::ini::Ini;
 use std::collections::HashMap;
@@ -16,9 +18,29 @@ static DEFAULT_TAGS: &[&str] = &[
     "Label",
 ];
 
+static DEFAULT_ALBUM_TAGS: &[&str] = &[
+    "Album",
+    "AlbumArtist",
+    "Artist",
+    "Date",
+    "Label",
+    "CatalogNumber",
+    "Genre",
+];
+
 pub struct Config {
     pub hosts: Vec<Host>,
     pub tags: Vec<Tag>,
+    // Stickers shown by current, after the tags.
+    pub stickers: Vec<Tag>,
+    // Tags shown by album-info.
+    pub album_tags: Vec<Tag>,
+    pub music_directory: Option<String>,
+    pub formats: HashMap<String, String>,
+    pub bar: Bar,
+    pub theme: Theme,
+    pub fuzzy: Fuzzy,
+    pub smart: Vec<Smart>,
 }
 
 impl Config {
@@ -44,6 +66,59 @@ pub struct Tag {
     pub label: Option<String>,
 }
 
+pub struct Bar {
+    pub format: Option<String>,
+    pub max_length: Option<usize>,
+    pub play_icon: String,
+    pub pause_icon: String,
+    pub stop_icon: String,
+}
+
+impl Default for Bar {
+    fn default() -> Self {
+        Bar {
+            format: None,
+            max_length: None,
+            play_icon: "▶".to_string(),
+            pause_icon: "⏸".to_string(),
+            stop_icon: String::new(),
+        }
+    }
+}
+
+// Settings for search --fuzzy.
+pub struct Fuzzy {
+    pub tags: Vec<String>,
+    pub limit: usize,
+}
+
+impl Default for Fuzzy {
+    fn default() -> Self {
+        Fuzzy {
+            tags: ["artist", "album", "title", "composer", "work"]
+                .iter()
+                .map(|t| t.to_string())
+                .collect(),
+            limit: 20,
+        }
+    }
+}
+
+// A smart playlist, the songs matching a query, defined in the smart section
+// as name=query, with the options name.sort, name.limit and name.random.
+pub struct Smart {
+    pub name: String,
+    // The query as written in the config file.
+    pub text: String,
+    pub query: SearchQuery,
+    // The tag to sort by, as with search --sort.
+    pub sort: Option<String>,
+    // Only the first songs after sorting.
+    pub limit: Option<usize>,
+    // A random sample of this many of the songs.
+    pub random: Option<usize>,
+}
+
 pub struct Host {
     pub host: String,
     pub label: String,
@@ -53,13 +128,15 @@ impl Default for Config {
     fn default() -> Self {
         Config {
             hosts: Vec::new(),
-            tags: DEFAULT_TAGS
-                .iter()
-                .map(|t| Tag {
-                    tag: (*t).to_string(),
-                    label: None,
-                })
-                .collect(),
+            tags: default_tags(DEFAULT_TAGS),
+            stickers: Vec::new(),
+            album_tags: default_tags(DEFAULT_ALBUM_TAGS),
+            music_directory: None,
+            formats: HashMap::new(),
+            bar: Bar::default(),
+            theme: Theme::default(),
+            fuzzy: Fuzzy::default(),
+            smart: Vec::new(),
         }
     }
 }
@@ -103,11 +180,55 @@ fn parse_config(map: &HashMap<String, HashMap<String, Option<String>>>) -> Resul
         .and_then(parse_tags)
         .unwrap_or_else(|| Config::default().tags);
 
-    Ok(Config { hosts, tags })
+    let stickers = map.get("stickers").and_then(parse_tags).unwrap_or_default();
+
+    let album_tags = map
+        .get("album_info")
+        .and_then(parse_tags)
+        .unwrap_or_else(|| Config::default().album_tags);
+
+    let music_directory = map
+        .get("settings")
+        .and_then(|s| s.get("music_directory"))
+        .and_then(Clone::clone);
+
+    let formats = map.get("formats").map_or_else(HashMap::new, |f| {
+        f.iter()
+            .filter_map(|(name, format)| Some((name.clone(), format.clone()?)))
+            .collect()
+    });
+
+    let bar = map
+        .get("bar")
+        .map_or_else(|| Ok(Bar::default()), parse_bar)?;
+
+    let theme = map
+        .get("theme")
+        .map_or_else(|| Ok(Theme::default()), parse_theme)?;
+
+    let fuzzy = map
+        .get("fuzzy")
+        .map_or_else(|| Ok(Fuzzy::default()), parse_fuzzy)?;
+
+    let smart = map.get("smart").map_or_else(|| Ok(vec![]), parse_smart)?;
+
+    Ok(Config {
+        hosts,
+        tags,
+        stickers,
+        album_tags,
+        music_directory,
+        formats,
+        bar,
+        theme,
+        fuzzy,
+        smart,
+    })
 }
 
 fn parse_hosts(map: &HashMap<String, Option<String>>) -> Result<Vec<Host>, Error> {
-    map.iter()
+    let mut hosts = map
+        .iter()
         .map(|(label, host)| {
             Ok(Host {
                 host: host.clone().ok_or_else(|| {
@@ -116,7 +237,122 @@ fn parse_hosts(map: &HashMap<String, Option<String>>) -> Result<Vec<Host>, Error
                 label: label.clone(),
             })
         })
-        .collect::<Result<Vec<Host>, Error>>()
+        .collect::<Result<Vec<Host>, Error>>()?;
+    hosts.sort_by(|a, b| a.label.cmp(&b.label));
+    Ok(hosts)
+}
+
+fn parse_bar(map: &HashMap<String, Option<String>>) -> Result<Bar, Error> {
+    let get = |key: &str| map.get(key).cloned().flatten();
+    let default = Bar::default();
+    Ok(Bar {
+        format: get("format"),
+        max_length: get("max_length")
+            .map(|l| {
+                l.parse()
+                    .map_err(|_| Error::Config(format!("Invalid bar max_length: {}", l)))
+            })
+            .transpose()?,
+        play_icon: get("play_icon").unwrap_or(default.play_icon),
+        pause_icon: get("pause_icon").unwrap_or(default.pause_icon),
+        stop_icon: get("stop_icon").unwrap_or(default.stop_icon),
+    })
+}
+
+fn parse_theme(map: &HashMap<String, Option<String>>) -> Result<Theme, Error> {
+    let mut theme = Theme::default();
+    for (element, style) in map {
+        let target = theme
+            .element_mut(element)
+            .ok_or_else(|| Error::Config(format!("Unknown theme element: {}", element)))?;
+        *target = style
+            .as_deref()
+            .unwrap_or_default()
+            .parse()
+            .map_err(|e| Error::Config(format!("Invalid style for {}: {}", element, e)))?;
+    }
+    Ok(theme)
+}
+
+fn parse_fuzzy(map: &HashMap<String, Option<String>>) -> Result<Fuzzy, Error> {
+    let get = |key: &str| map.get(key).cloned().flatten();
+    let default = Fuzzy::default();
+    Ok(Fuzzy {
+        tags: get("tags").map_or(default.tags, |t| {
+            t.split(',').map(|t| t.trim().to_string()).collect()
+        }),
+        limit: get("limit")
+            .map(|l| {
+                l.parse()
+                    .map_err(|_| Error::Config(format!("Invalid fuzzy limit: {}", l)))
+            })
+            .transpose()?
+            .unwrap_or(default.limit),
+    })
+}
+
+fn parse_smart(map: &HashMap<String, Option<String>>) -> Result<Vec<Smart>, Error> {
+    for key in map.keys() {
+        match key.split_once('.') {
+            Some((name, _)) if !map.contains_key(name) => {
+                return Err(Error::Config(format!(
+                    "Missing query for smart playlist {}.",
+                    name
+                )));
+            }
+            Some((_, option)) if !["sort", "limit", "random"].contains(&option) => {
+                return Err(Error::Config(format!(
+                    "Unknown smart playlist option: {}",
+                    key
+                )));
+            }
+            _ => (),
+        }
+    }
+    let mut playlists = map
+        .iter()
+        .filter(|(key, _)| !key.contains('.'))
+        .map(|(name, text)| {
+            let text = text.clone().ok_or_else(|| {
+                Error::Config(format!("Missing query for smart playlist {}.", name))
+            })?;
+            let query = SearchQuery::from_text(&text).map_err(|e| {
+                Error::Config(format!("Invalid query for smart playlist {}: {}", name, e))
+            })?;
+            let get = |option: &str| map.get(&format!("{}.{}", name, option)).cloned().flatten();
+            let number = |option: &str| {
+                get(option)
+                    .map(|n| {
+                        n.parse().map_err(|_| {
+                            Error::Config(format!(
+                                "Invalid {} for smart playlist {}: {}",
+                                option, name, n
+                            ))
+                        })
+                    })
+                    .transpose()
+            };
+            Ok(Smart {
+                name: name.clone(),
+                text,
+                query,
+                sort: get("sort"),
+                limit: number("limit")?,
+                random: number("random")?,
+            })
+        })
+        .collect::<Result<Vec<_>, Error>>()?;
+    playlists.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(playlists)
+}
+
+fn default_tags(tags: &[&str]) -> Vec<Tag> {
+    tags.iter()
+        .map(|t| Tag {
+            tag: (*t).to_string(),
+            label: None,
+        })
+        .collect()
 }
 
 fn parse_tags(map: &HashMap<String, Option<String>>) -> Option<Vec<Tag>> {
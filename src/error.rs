@@ -11,6 +11,10 @@ pub enum Error {
     ArgParse(lexopt::Error),
     ParseSeek(&'static str),
     Config(String),
+    Playlist(String),
+    ParseFormat(String),
+    Filter(String),
+    UnknownSmartPlaylist(String),
 }
 
 impl StdErr for Error {}
@@ -55,6 +59,18 @@ impl fmt::Display for Error {
             Error::Config(e) => {
                 write!(f, "Failed to parse config file:\n{}", e)
             }
+            Error::Playlist(e) => {
+                write!(f, "Failed to handle playlist file:\n{}", e)
+            }
+            Error::ParseFormat(e) => {
+                write!(f, "Couldn't parse format string:\n{}", e)
+            }
+            Error::Filter(e) => {
+                write!(f, "Couldn't evaluate the query on the cache:\n{}", e)
+            }
+            Error::UnknownSmartPlaylist(name) => {
+                write!(f, "No smart playlist named {} in the config file.", name)
+            }
         }
     }
 }
@@ -0,0 +1,449 @@
+use crate::tags::{native_tags, Tags};
+use mpdrs::Song;
+use regex::{Regex, RegexBuilder};
+use std::fmt;
+
+// MPD filter expressions, evaluated locally against the songs of the cache,
+// such as:
+//
+//     ((artist == "Miles Davis") AND (!(genre contains "live")))
+//
+// As with MPD, a filter for search compares case insensitively, and one for
+// find compares exactly.
+pub struct Filter {
+    expression: Expression,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct Error(String);
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid filter, {}", self.0)
+    }
+}
+
+// Values which MPD reports along with the tags of a song.
+const NON_TAGS: &[&str] = &["duration", "format", "added"];
+
+enum Expression {
+    Not(Box<Expression>),
+    And(Vec<Expression>),
+    // A tag, an operator, and whether to compare case insensitively.
+    Compare(String, Op, bool),
+    Base(String),
+    // The modification time in seconds since the epoch.
+    ModifiedSince(i64),
+    // The time the song was added to the database, which MPD reports as
+    // Added since version 0.24.
+    AddedSince(i64),
+}
+
+enum Op {
+    Equal(String),
+    NotEqual(String),
+    Contains(String),
+    StartsWith(String),
+    Matches(Regex),
+    NotMatches(Regex),
+}
+
+impl Filter {
+    // Parse an MPD filter expression.
+    pub fn parse(s: &str, ignore_case: bool) -> Result<Filter, Error> {
+        let mut parser = Parser {
+            chars: s.chars().collect(),
+            pos: 0,
+            ignore_case,
+        };
+        let expression = parser.expression()?;
+        parser.skip_spaces();
+        if parser.pos < parser.chars.len() {
+            return Err(Error("unexpected text after the expression".to_string()));
+        }
+        Ok(Filter { expression })
+    }
+
+    // The filter of tag and value pairs, which search matches as substrings
+    // and find as equal values.
+    pub fn pairs(pairs: &[(String, String)], ignore_case: bool) -> Filter {
+        let fold = |v: &str| {
+            if ignore_case {
+                v.to_lowercase()
+            } else {
+                v.to_string()
+            }
+        };
+        let terms = pairs
+            .iter()
+            .map(|(tag, value)| match &*tag.to_lowercase() {
+                "base" => Expression::Base(value.clone()),
+                tag if ignore_case => {
+                    Expression::Compare(tag.to_string(), Op::Contains(fold(value)), true)
+                }
+                tag => Expression::Compare(tag.to_string(), Op::Equal(value.clone()), false),
+            })
+            .collect();
+        Filter {
+            expression: Expression::And(terms),
+        }
+    }
+
+    pub fn matches(&self, song: &Song) -> bool {
+        self.expression.matches(song)
+    }
+}
+
+impl Expression {
+    fn matches(&self, song: &Song) -> bool {
+        match self {
+            Expression::Not(e) => !e.matches(song),
+            Expression::And(es) => es.iter().all(|e| e.matches(song)),
+            Expression::Base(base) => {
+                base.is_empty()
+                    || song
+                        .file
+                        .strip_prefix(base.trim_end_matches('/'))
+                        .is_some_and(|rest| rest.starts_with('/'))
+            }
+            Expression::ModifiedSince(since) => song
+                .last_mod
+                .as_deref()
+                .and_then(timestamp)
+                .is_some_and(|t| t >= *since),
+            Expression::AddedSince(since) => song
+                .tags
+                .iter()
+                .find(|(k, _)| k == "Added")
+                .and_then(|(_, v)| timestamp(v))
+                .is_some_and(|t| t >= *since),
+            Expression::Compare(tag, op, ignore_case) => {
+                let tags = Tags::from_song(song);
+                let native = native_tags(song);
+                let values = match &**tag {
+                    "file" => vec![&*song.file],
+                    "audioformat" => tags.get("format"),
+                    "any" => native
+                        .iter()
+                        .filter(|(k, _)| !NON_TAGS.iter().any(|n| k.eq_ignore_ascii_case(n)))
+                        .map(|(_, v)| &**v)
+                        .collect(),
+                    tag => tags.get(tag),
+                };
+                op.matches(&values, *ignore_case)
+            }
+        }
+    }
+}
+
+impl Op {
+    // Whether any of the values of a tag matches. Values of missing tags are
+    // the empty string, as in MPD. Case insensitive comparisons are folded
+    // when parsed, so only the values need to be folded here.
+    fn matches(&self, values: &[&str], ignore_case: bool) -> bool {
+        let values = if values.is_empty() { &[""] } else { values };
+        let any = |f: &dyn Fn(&str) -> bool| {
+            values.iter().any(|value| match ignore_case {
+                true => f(&value.to_lowercase()),
+                false => f(value),
+            })
+        };
+        match self {
+            Op::Equal(v) => any(&|value| value == v),
+            Op::NotEqual(v) => !any(&|value| value == v),
+            Op::Contains(v) => any(&|value| value.contains(&**v)),
+            Op::StartsWith(v) => any(&|value| value.starts_with(&**v)),
+            Op::Matches(r) => values.iter().any(|value| r.is_match(value)),
+            Op::NotMatches(r) => !values.iter().any(|value| r.is_match(value)),
+        }
+    }
+}
+
+// Seconds since the epoch of a time such as 2020-01-01T12:00:00Z, as MPD
+// reports modification times.
+fn timestamp(s: &str) -> Option<i64> {
+    let number = |range: std::ops::Range<usize>| s.get(range)?.parse::<i32>().ok();
+    let mut tm: libc::tm = unsafe { std::mem::zeroed() };
+    tm.tm_year = number(0..4)? - 1900;
+    tm.tm_mon = number(5..7)? - 1;
+    tm.tm_mday = number(8..10)?;
+    tm.tm_hour = number(11..13).unwrap_or(0);
+    tm.tm_min = number(14..16).unwrap_or(0);
+    tm.tm_sec = number(17..19).unwrap_or(0);
+    Some(unsafe { libc::timegm(&mut tm) } as i64)
+}
+
+// Compile a regular expression of a filter. MPD uses PCRE, where a { which
+// does not start a repetition such as {2} or {2,5} is a literal character,
+// so such braces are escaped first.
+fn regex(pattern: &str, ignore_case: bool) -> Result<Regex, Error> {
+    let chars = pattern.chars().collect::<Vec<_>>();
+    let mut escaped = String::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        match c {
+            '\\' => {
+                escaped.extend(&chars[i..(i + 2).min(chars.len())]);
+                i += 2;
+                continue;
+            }
+            '{' if !is_repetition(&chars[i + 1..]) => escaped.push('\\'),
+            _ => (),
+        }
+        escaped.push(c);
+        i += 1;
+    }
+    RegexBuilder::new(&escaped)
+        .case_insensitive(ignore_case)
+        .build()
+        .map_err(|e| {
+            // The message of a syntax error points at the pattern on lines
+            // of its own.
+            let message = e.to_string();
+            let message = message.lines().last().unwrap_or_default();
+            let message = message.trim_start_matches("error: ");
+            Error(format!(
+                "invalid regular expression {}: {}",
+                pattern, message
+            ))
+        })
+}
+
+// Whether chars, after a {, are the rest of a repetition: a number, followed
+// by }, ,} or a second number and }.
+fn is_repetition(chars: &[char]) -> bool {
+    let digits = |chars: &[char]| chars.iter().take_while(|c| c.is_ascii_digit()).count();
+    let min = digits(chars);
+    if min == 0 {
+        return false;
+    }
+    match chars.get(min) {
+        Some('}') => true,
+        Some(',') => {
+            let max = digits(&chars[min + 1..]);
+            chars.get(min + 1 + max) == Some(&'}')
+        }
+        _ => false,
+    }
+}
+
+struct Parser {
+    chars: Vec<char>,
+    pos: usize,
+    ignore_case: bool,
+}
+
+impl Parser {
+    fn skip_spaces(&mut self) {
+        while self.chars.get(self.pos).is_some_and(|c| c.is_whitespace()) {
+            self.pos += 1;
+        }
+    }
+
+    fn expect(&mut self, s: &str) -> Result<(), Error> {
+        self.skip_spaces();
+        if self.chars[self.pos..].starts_with(&s.chars().collect::<Vec<_>>()) {
+            self.pos += s.chars().count();
+            Ok(())
+        } else {
+            Err(Error(format!("expected {} at column {}", s, self.pos + 1)))
+        }
+    }
+
+    // A word such as a tag, an operator or AND.
+    fn word(&mut self) -> String {
+        self.skip_spaces();
+        let start = self.pos;
+        while self
+            .chars
+            .get(self.pos)
+            .is_some_and(|c| !c.is_whitespace() && !"()'\"".contains(*c))
+        {
+            self.pos += 1;
+        }
+        self.chars[start..self.pos].iter().collect()
+    }
+
+    fn string(&mut self) -> Result<String, Error> {
+        self.skip_spaces();
+        let quote = match self.chars.get(self.pos) {
+            Some(c @ ('"' | '\'')) => *c,
+            _ => {
+                return Err(Error(format!(
+                    "expected a string at column {}",
+                    self.pos + 1
+                )))
+            }
+        };
+        self.pos += 1;
+        let mut s = String::new();
+        loop {
+            match self.chars.get(self.pos) {
+                None => return Err(Error("unterminated string".to_string())),
+                Some('\\') => {
+                    self.pos += 1;
+                    s.extend(self.chars.get(self.pos));
+                }
+                Some(c) if *c == quote => break,
+                Some(c) => s.push(*c),
+            }
+            self.pos += 1;
+        }
+        self.pos += 1;
+        Ok(s)
+    }
+
+    fn expression(&mut self) -> Result<Expression, Error> {
+        self.expect("(")?;
+        self.skip_spaces();
+        let expression = match self.chars.get(self.pos) {
+            Some('!') => {
+                self.pos += 1;
+                Expression::Not(Box::new(self.expression()?))
+            }
+            Some('(') => {
+                let mut expressions = vec![self.expression()?];
+                loop {
+                    self.skip_spaces();
+                    if self.chars.get(self.pos) == Some(&')') {
+                        break;
+                    }
+                    if self.word() != "AND" {
+                        return Err(Error(format!("expected AND at column {}", self.pos + 1)));
+                    }
+                    expressions.push(self.expression()?);
+                }
+                Expression::And(expressions)
+            }
+            _ => self.comparison()?,
+        };
+        self.expect(")")?;
+        Ok(expression)
+    }
+
+    fn comparison(&mut self) -> Result<Expression, Error> {
+        let tag = self.word().to_lowercase();
+        match &*tag {
+            "base" => return Ok(Expression::Base(self.string()?)),
+            "modified-since" | "added-since" => {
+                let value = self.string()?;
+                let since = value
+                    .parse()
+                    .ok()
+                    .or_else(|| timestamp(&value))
+                    .ok_or_else(|| Error(format!("invalid time: {}", value)))?;
+                return Ok(match &*tag {
+                    "added-since" => Expression::AddedSince(since),
+                    _ => Expression::ModifiedSince(since),
+                });
+            }
+            "" => return Err(Error(format!("expected a tag at column {}", self.pos + 1))),
+            _ => (),
+        }
+        let operator = self.word();
+        let value = self.string()?;
+        let ignore_case = match &*operator {
+            "eq_cs" | "contains_cs" | "starts_with_cs" => false,
+            "eq_ci" | "contains_ci" | "starts_with_ci" => true,
+            _ => self.ignore_case,
+        };
+        let folded = if ignore_case {
+            value.to_lowercase()
+        } else {
+            value.clone()
+        };
+        let op = match &*operator {
+            "==" | "eq_cs" | "eq_ci" => Op::Equal(folded),
+            "!=" => Op::NotEqual(folded),
+            "contains" | "contains_cs" | "contains_ci" => Op::Contains(folded),
+            "starts_with" | "starts_with_cs" | "starts_with_ci" => Op::StartsWith(folded),
+            "=~" => Op::Matches(regex(&value, ignore_case)?),
+            "!~" => Op::NotMatches(regex(&value, ignore_case)?),
+            op => return Err(Error(format!("unknown operator: {}", op))),
+        };
+        Ok(Expression::Compare(tag, op, ignore_case))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn song() -> Song {
+        Song {
+            file: "Miles Davis/Kind of Blue/01 So What.flac".into(),
+            artist: Some("Miles Davis".into()),
+            title: Some("So What".into()),
+            last_mod: Some("2020-01-01T00:00:00Z".into()),
+            tags: vec![
+                ("Album".into(), "Kind of Blue".into()),
+                ("Date".into(), "1959-08-17".into()),
+                ("Performer".into(), "John Coltrane".into()),
+                ("Performer".into(), "Bill Evans".into()),
+                ("Added".into(), "2021-06-01T00:00:00Z".into()),
+            ],
+            ..Song::default()
+        }
+    }
+
+    fn matches(filter: &str, ignore_case: bool) -> bool {
+        Filter::parse(filter, ignore_case).unwrap().matches(&song())
+    }
+
+    #[test]
+    fn expressions() {
+        assert!(matches("(artist == 'Miles Davis')", false));
+        assert!(!matches("(artist == 'miles davis')", false));
+        assert!(matches("(artist == 'miles davis')", true));
+        assert!(matches("(performer == \"Bill Evans\")", false));
+        assert!(matches("(genre == '')", false));
+        assert!(matches("(genre != 'Jazz')", false));
+        assert!(matches("(any contains 'coltrane')", true));
+        assert!(matches("(date =~ '^0*(19[5-9][0-9])([^0-9]|$)')", false));
+        assert!(matches("(base 'Miles Davis')", false));
+        assert!(!matches("(base 'Miles')", false));
+        assert!(matches("(modified-since '1577836800')", false));
+        assert!(!matches("(modified-since '2020-01-02')", false));
+        assert!(matches("(added-since '2021-01-01')", false));
+        assert!(!matches("(added-since '2021-06-02')", false));
+        assert!(!matches("(any contains '2021')", true));
+        assert!(matches(
+            "((album contains 'Blue') AND (!(title == 'Freddie Freeloader')))",
+            false
+        ));
+        assert!(!matches("(!(!(title starts_with 'Freddie')))", false));
+        assert!(matches("(album =~ '^Kind {1,2}of')", false));
+        assert!(matches("(album =~ '^kind of (blue|red)$')", true));
+    }
+
+    #[test]
+    fn regular_expressions() {
+        let matches = |pattern: &str, text: &str| regex(pattern, false).unwrap().is_match(text);
+        // Braces which do not start a repetition are literal, as in PCRE.
+        assert!(matches("^a{$", "a{"));
+        assert!(matches("^a{,2}$", "a{,2}"));
+        assert!(matches("^[{]x{2}\\{$", "{xx{"));
+        assert!(!matches("^a{2}$", "a{2}"));
+        // Matching does not backtrack exponentially.
+        assert!(!matches("^(a|a)*b", &"a".repeat(100)));
+        assert!(regex("(a", false).is_err());
+        assert!(regex("a{3,1}", false).is_err());
+    }
+
+    #[test]
+    fn pairs() {
+        let pair = |tag: &str, value: &str| vec![(tag.to_string(), value.to_string())];
+        assert!(Filter::pairs(&pair("album", "blue"), true).matches(&song()));
+        assert!(!Filter::pairs(&pair("album", "blue"), false).matches(&song()));
+        assert!(Filter::pairs(&pair("album", "Kind of Blue"), false).matches(&song()));
+    }
+
+    #[test]
+    fn errors() {
+        assert!(Filter::parse("(artist == 'x'", false).is_err());
+        assert!(Filter::parse("(artist ~ 'x')", false).is_err());
+        assert!(Filter::parse("((a == 'x') OR (b == 'y'))", false).is_err());
+        assert!(Filter::parse("(a =~ '(')", false).is_err());
+    }
+}
@@ -0,0 +1,146 @@
+use crate::ansi::terminal_height;
+use crate::error::{Error, WithContext};
+use crate::raw;
+use std::io::Write;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+const TICK: Duration = Duration::from_secs(1);
+
+static STOP: AtomicBool = AtomicBool::new(false);
+
+// A view which is redrawn whenever MPD reports a change.
+pub trait View {
+    // The MPD subsystems which affect the view.
+    fn subsystems(&self) -> &'static [&'static str];
+
+    // Fetch the state of the view from MPD.
+    fn update(&mut self, client: &mut mpdrs::Client) -> Result<(), Error>;
+
+    // Render the view, as if `since_update` had passed since the last update.
+    fn render(&self, since_update: Duration) -> String;
+
+    // The terminal title for the view.
+    fn title(&self) -> Option<String>;
+}
+
+// Status as it would be after playing for `by`, for ticking the elapsed time
+// between updates.
+pub fn advance(status: &mpdrs::Status, by: Duration) -> mpdrs::Status {
+    let mut status = status.clone();
+    if status.state == mpdrs::State::Play {
+        let limit = |elapsed: Duration, total: Option<Duration>| match total {
+            Some(total) if !total.is_zero() => (elapsed + by).min(total),
+            _ => elapsed + by,
+        };
+        status.elapsed = status.elapsed.map(|e| limit(e, status.duration));
+        status.time = status.time.map(|(e, total)| (limit(e, Some(total)), total));
+    }
+    status
+}
+
+extern "C" fn stop(_: libc::c_int) {
+    STOP.store(true, Ordering::Relaxed);
+}
+
+// Redraw the view until interrupted. On a terminal, the view is drawn on the
+// alternate screen and the elapsed time ticks every second, otherwise it is
+// printed again after every change.
+pub fn follow(
+    client: &mut mpdrs::Client,
+    raw: &mut raw::Connection,
+    view: &mut dyn View,
+    title: bool,
+) -> Result<(), Error> {
+    let handler = stop as extern "C" fn(libc::c_int) as libc::sighandler_t;
+    unsafe {
+        libc::signal(libc::SIGINT, handler);
+        libc::signal(libc::SIGTERM, handler);
+    }
+    let interactive = unsafe { libc::isatty(libc::STDOUT_FILENO) } == 1
+        && !matches!(std::env::var("TERM"), Ok(t) if t == "dumb");
+    let mut screen = Screen::new(interactive, title)?;
+
+    view.update(client)?;
+    let mut updated = Instant::now();
+    let mut changed = true;
+    while !STOP.load(Ordering::Relaxed) {
+        if changed || interactive {
+            screen.draw(&view.render(updated.elapsed()), view.title())?;
+        }
+        changed = raw.idle(view.subsystems(), TICK)?.is_some();
+        if changed {
+            view.update(client)?;
+            updated = Instant::now();
+        }
+    }
+    Ok(())
+}
+
+struct Screen {
+    interactive: bool,
+    title: bool,
+    first: bool,
+}
+
+impl Screen {
+    fn new(interactive: bool, title: bool) -> Result<Screen, Error> {
+        let screen = Screen {
+            interactive,
+            title,
+            first: true,
+        };
+        if interactive {
+            // Switch to the alternate screen, hide the cursor and save the
+            // terminal title.
+            screen.write("\x1B[?1049h\x1B[?25l\x1B[22;0t")?;
+        }
+        Ok(screen)
+    }
+
+    fn write(&self, s: &str) -> Result<(), Error> {
+        let mut out = std::io::stdout().lock();
+        out.write_all(s.as_bytes())
+            .and_then(|_| out.flush())
+            .context("writing to stdout")
+    }
+
+    fn draw(&mut self, content: &str, title: Option<String>) -> Result<(), Error> {
+        let mut out = String::new();
+        if self.interactive {
+            out.push_str("\x1B[H");
+            let height = terminal_height().unwrap_or(usize::MAX);
+            for (i, line) in content.lines().take(height).enumerate() {
+                if i != 0 {
+                    out.push('\n');
+                }
+                out.push_str(line);
+                out.push_str("\x1B[K");
+            }
+            out.push_str("\x1B[J");
+            if let Some(title) = title.filter(|_| self.title) {
+                out.push_str(&format!(
+                    "\x1B]2;{}\x07",
+                    title.replace(['\x07', '\x1B'], "")
+                ));
+            }
+        } else {
+            if !self.first {
+                out.push('\n');
+            }
+            out.push_str(content);
+        }
+        self.first = false;
+        self.write(&out)
+    }
+}
+
+impl Drop for Screen {
+    fn drop(&mut self) {
+        if self.interactive {
+            // Restore the title, show the cursor and leave the alternate
+            // screen.
+            let _ = self.write("\x1B[23;0t\x1B[?25h\x1B[?1049l");
+        }
+    }
+}
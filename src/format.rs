@@ -0,0 +1,300 @@
+use crate::config::Config;
+use crate::error::Error;
+use crate::query;
+use crate::status::duration_format;
+use crate::tags::Tags;
+use mpdrs::Song;
+
+// Tags which are not read from the song metadata, but from MPD.
+static SPECIAL_TAGS: &[&str] = &["file", "time", "position", "id", "mtime"];
+
+// A template is a list of alternatives separated by '|', where the first
+// alternative that can be rendered is used. Every alternative consists of
+// sequences joined by '&', all of which must be rendered.
+#[derive(Debug, PartialEq)]
+struct Expr(Vec<Vec<Vec<Node>>>);
+
+#[derive(Debug, PartialEq)]
+enum Node {
+    Text(String),
+    Tag(String),
+    Optional(Expr),
+}
+
+#[derive(Debug)]
+pub struct Template(Expr);
+
+impl Template {
+    // Use the named format from the config file if there is one, and
+    // otherwise parse the string as a template.
+    pub fn from_config_or_str(conf: &Config, format: &str) -> Result<Template, Error> {
+        match conf.formats.get(format) {
+            Some(template) => {
+                log::trace!("Using named format {}: {}", format, template);
+                template.parse()
+            }
+            None => format.parse(),
+        }
+    }
+
+    pub fn render(&self, song: &Song, tags: &Tags) -> String {
+        self.0.render(song, tags).unwrap_or_default()
+    }
+
+    // Whether the template uses a tag which MPD does not know about, in any
+    // section, and which tags do not have. MPD reports the tags it knows
+    // whenever the file has them.
+    fn needs_raw_comments(&self, tags: &Tags) -> bool {
+        let mut names = vec![];
+        self.0.tag_names(&mut names);
+        names
+            .iter()
+            .any(|t| !query::is_tag(t) && tags.get(t).is_empty())
+    }
+
+    // Tags for song, including the raw comments from the file if the template
+    // uses a tag which MPD does not know about, such as a catalog number.
+    pub fn tags_for(&self, client: &mut mpdrs::Client, song: &Song) -> Result<Tags, Error> {
+        let tags = Tags::from_song(song);
+        if !self.needs_raw_comments(&tags) {
+            return Ok(tags);
+        }
+        log::trace!("Template uses tags not known to MPD, reading raw comments.");
+        match client.readcomments(&song.file) {
+            Ok(comments) => Ok(Tags::from_song_and_raw_comments(
+                song,
+                comments.collect::<Result<_, _>>()?,
+            )),
+            Err(mpdrs::error::Error::Server(_)) => Ok(tags),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+impl std::str::FromStr for Template {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let chars = s.chars().collect::<Vec<_>>();
+        let mut pos = 0;
+        let expr = parse_expr(&chars, &mut pos)?;
+        if pos < chars.len() {
+            return Err(Error::ParseFormat(format!(
+                "unmatched ']' at column {}",
+                pos + 1
+            )));
+        }
+        Ok(Template(expr))
+    }
+}
+
+impl Expr {
+    fn render(&self, song: &Song, tags: &Tags) -> Option<String> {
+        self.0.iter().find_map(|conjunction| {
+            conjunction
+                .iter()
+                .map(|sequence| render_sequence(sequence, song, tags))
+                .collect::<Option<String>>()
+        })
+    }
+
+    // The tags used by the expression, including those in optional sections,
+    // except the ones read from MPD rather than from the song metadata.
+    fn tag_names<'a>(&'a self, names: &mut Vec<&'a str>) {
+        for node in self.0.iter().flatten().flatten() {
+            match node {
+                Node::Tag(t) if !SPECIAL_TAGS.contains(&&*t.to_lowercase()) => names.push(t),
+                Node::Optional(e) => e.tag_names(names),
+                _ => (),
+            }
+        }
+    }
+}
+
+fn render_sequence(sequence: &[Node], song: &Song, tags: &Tags) -> Option<String> {
+    let mut out = String::new();
+    for node in sequence {
+        match node {
+            Node::Text(s) => out.push_str(s),
+            Node::Tag(t) => out.push_str(&lookup(t, song, tags)?),
+            Node::Optional(e) => out.push_str(&e.render(song, tags).unwrap_or_default()),
+        }
+    }
+    Some(out)
+}
+
+pub fn lookup(tag: &str, song: &Song, tags: &Tags) -> Option<String> {
+    match &*tag.to_lowercase() {
+        "file" => Some(song.file.clone()),
+        "time" => song.duration.map(|d| duration_format(&d)),
+        "position" => song.place.map(|p| (p.pos + 1).to_string()),
+        "id" => song.place.map(|p| p.id.to_string()),
+        "mtime" => song.last_mod.clone(),
+        _ => tags.get_option_joined(tag),
+    }
+}
+
+fn parse_expr(chars: &[char], pos: &mut usize) -> Result<Expr, Error> {
+    let mut alternatives = vec![vec![vec![]]];
+    while let Some(&c) = chars.get(*pos) {
+        *pos += 1;
+        let sequence = alternatives
+            .last_mut()
+            .and_then(|a| a.last_mut())
+            .expect("alternatives can not be empty");
+        match c {
+            '|' => alternatives.push(vec![vec![]]),
+            '&' => alternatives
+                .last_mut()
+                .expect("alternatives can not be empty")
+                .push(vec![]),
+            '[' => {
+                let start = *pos;
+                let inner = parse_expr(chars, pos)?;
+                if chars.get(*pos) != Some(&']') {
+                    return Err(Error::ParseFormat(format!(
+                        "unclosed '[' at column {}",
+                        start
+                    )));
+                }
+                *pos += 1;
+                sequence.push(Node::Optional(inner));
+            }
+            ']' => {
+                *pos -= 1;
+                break;
+            }
+            '%' => {
+                let start = *pos;
+                let len = chars[start..]
+                    .iter()
+                    .position(|c| *c == '%')
+                    .ok_or_else(|| {
+                        Error::ParseFormat(format!("unclosed '%' at column {}", start))
+                    })?;
+                let tag = chars[start..start + len].iter().collect::<String>();
+                if tag.is_empty() {
+                    return Err(Error::ParseFormat(format!(
+                        "empty tag name at column {}",
+                        start
+                    )));
+                }
+                *pos += len + 1;
+                sequence.push(Node::Tag(tag));
+            }
+            '\\' => {
+                let escaped = match chars.get(*pos) {
+                    Some('n') => '\n',
+                    Some('t') => '\t',
+                    Some(c) => *c,
+                    None => {
+                        return Err(Error::ParseFormat(format!(
+                            "dangling '\\' at column {}",
+                            *pos
+                        )))
+                    }
+                };
+                *pos += 1;
+                push_char(sequence, escaped);
+            }
+            c => push_char(sequence, c),
+        }
+    }
+    Ok(Expr(alternatives))
+}
+
+fn push_char(sequence: &mut Vec<Node>, c: char) {
+    if let Some(Node::Text(s)) = sequence.last_mut() {
+        s.push(c);
+    } else {
+        sequence.push(Node::Text(c.to_string()));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::time::Duration;
+
+    fn song() -> Song {
+        Song {
+            file: "dir/file.flac".into(),
+            title: Some("So What".into()),
+            duration: Some(Duration::from_secs(545)),
+            tags: vec![("Album".into(), "Kind of Blue".into())],
+            ..Song::default()
+        }
+    }
+
+    fn render(template: &str, song: &Song) -> String {
+        let template = template.parse::<Template>().unwrap();
+        template.render(song, &Tags::from_song(song))
+    }
+
+    #[test]
+    fn plain_tags() {
+        assert_eq!(
+            render("%title% (%album%)", &song()),
+            "So What (Kind of Blue)"
+        );
+        assert_eq!(render("%file% %time%", &song()), "dir/file.flac 09:05");
+    }
+
+    #[test]
+    fn optional_sections() {
+        assert_eq!(render("[%artist% - ]%title%", &song()), "So What");
+        let mut s = song();
+        s.artist = Some("Miles Davis".into());
+        assert_eq!(render("[%artist% - ]%title%", &s), "Miles Davis - So What");
+    }
+
+    #[test]
+    fn fallbacks() {
+        assert_eq!(render("%artist%|%file%", &song()), "dir/file.flac");
+        assert_eq!(
+            render("[%artist%|%album%] %title%", &song()),
+            "Kind of Blue So What"
+        );
+        assert_eq!(render("[%album%&%artist%]", &song()), "");
+        assert_eq!(render("%artist%", &song()), "");
+    }
+
+    #[test]
+    fn escapes() {
+        assert_eq!(render("\\[%title%\\]\\|\\%", &song()), "[So What]|%");
+    }
+
+    #[test]
+    fn raw_comment_tags() {
+        let template = "%title% %catalognumber%".parse::<Template>().unwrap();
+        assert!(template.needs_raw_comments(&Tags::from_song(&song())));
+        let tags = Tags::from_song_and_raw_comments(
+            &song(),
+            vec![("CATALOGNUMBER".into(), "CL 1355".into())],
+        );
+        assert_eq!(template.render(&song(), &tags), "So What CL 1355");
+    }
+
+    #[test]
+    fn raw_comments_for_unknown_tags() {
+        let needs = |template: &str| {
+            let template = template.parse::<Template>().unwrap();
+            template.needs_raw_comments(&Tags::from_song(&song()))
+        };
+        assert!(needs("%title%[ %catalognumber%]"));
+        assert!(needs("%catalognumber%|%title%"));
+        assert!(!needs("%title%[ %artist%]"));
+        assert!(!needs("%title%|%position%"));
+    }
+
+    #[test]
+    fn parse_errors() {
+        let err = |s: &str| match s.parse::<Template>() {
+            Err(Error::ParseFormat(e)) => e,
+            _ => panic!("expected parse error for {}", s),
+        };
+        assert_eq!(err("[%title%"), "unclosed '[' at column 1");
+        assert_eq!(err("ab %title"), "unclosed '%' at column 4");
+        assert_eq!(err("%title%]"), "unmatched ']' at column 8");
+    }
+}
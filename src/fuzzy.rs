@@ -0,0 +1,229 @@
+use crate::tags::Tags;
+use mpdrs::Song;
+use std::collections::HashMap;
+use std::str::FromStr;
+
+// Scores of a fuzzy match: every matched character scores, with bonuses for
+// characters at the start of a word and for consecutive characters, and a
+// penalty for every character skipped between two matches.
+const MATCH: i64 = 16;
+const WORD_START: i64 = 8;
+const CONSECUTIVE: i64 = 12;
+const GAP: i64 = 1;
+
+// What to rank: songs, albums or the values of a tag.
+pub enum Kind {
+    Songs,
+    Albums,
+    Tag(String),
+}
+
+impl FromStr for Kind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "songs" => Ok(Kind::Songs),
+            "albums" => Ok(Kind::Albums),
+            "" => Err("expected songs, albums or a tag".to_string()),
+            tag => Ok(Kind::Tag(tag.to_string())),
+        }
+    }
+}
+
+// A song, album or tag value, and the songs it consists of.
+pub struct Candidate {
+    pub label: String,
+    pub songs: Vec<Song>,
+    fields: Vec<String>,
+}
+
+// Group songs into candidates of the given kind, which are matched against
+// the values of tags.
+pub fn candidates(songs: Vec<Song>, kind: &Kind, tags: &[String]) -> Vec<Candidate> {
+    let fields = |song: &Song| {
+        let song_tags = Tags::from_song(song);
+        tags.iter()
+            .flat_map(|tag| song_tags.get(tag))
+            .map(str::to_string)
+            .collect::<Vec<_>>()
+    };
+    let mut candidates: Vec<Candidate> = vec![];
+    let mut index = HashMap::new();
+    for song in songs {
+        let keys = match kind {
+            Kind::Songs => vec![song.file.clone()],
+            Kind::Albums => {
+                let song_tags = Tags::from_song(&song);
+                let artist = song_tags
+                    .get_option_joined("albumartist")
+                    .or_else(|| song_tags.get_option_joined("artist"));
+                match (artist, song_tags.get_option_joined("album")) {
+                    (Some(artist), Some(album)) => vec![format!("{} - {}", artist, album)],
+                    (None, Some(album)) => vec![album],
+                    (_, None) => vec![],
+                }
+            }
+            Kind::Tag(tag) => Tags::from_song(&song)
+                .get(tag)
+                .into_iter()
+                .map(str::to_string)
+                .collect(),
+        };
+        for key in keys {
+            let i = *index.entry(key.clone()).or_insert_with(|| {
+                candidates.push(Candidate {
+                    fields: match kind {
+                        Kind::Songs => fields(&song),
+                        _ => vec![key.clone()],
+                    },
+                    label: key,
+                    songs: vec![],
+                });
+                candidates.len() - 1
+            });
+            let candidate = &mut candidates[i];
+            if let Kind::Albums = kind {
+                for field in fields(&song) {
+                    if !candidate.fields.contains(&field) {
+                        candidate.fields.push(field);
+                    }
+                }
+            }
+            candidate.songs.push(song.clone());
+        }
+    }
+    candidates
+}
+
+// The best limit candidates matching text, best first.
+pub fn rank(candidates: Vec<Candidate>, text: &str, limit: usize) -> Vec<Candidate> {
+    let mut scored = candidates
+        .into_iter()
+        .filter_map(|c| Some((score(text, &c.fields)?, c)))
+        .collect::<Vec<_>>();
+    scored.sort_by_key(|(score, _)| std::cmp::Reverse(*score));
+    scored.into_iter().take(limit).map(|(_, c)| c).collect()
+}
+
+// Every word of text has to match one of the fields. The score is the sum
+// of the best score of every word.
+fn score(text: &str, fields: &[String]) -> Option<i64> {
+    let fields = fields.iter().map(|f| fold(f)).collect::<Vec<_>>();
+    text.split_whitespace()
+        .map(|word| {
+            let word = fold(word);
+            fields.iter().filter_map(|f| score_word(&word, f)).max()
+        })
+        .sum()
+}
+
+fn fold(s: &str) -> Vec<char> {
+    s.chars().flat_map(char::to_lowercase).collect()
+}
+
+fn is_word_start(text: &[char], i: usize) -> bool {
+    i == 0 || !text[i - 1].is_alphanumeric()
+}
+
+// The best score of the characters of pattern appearing in order in text, or
+// None if they don't. best[j] is the best score of the pattern so far with
+// its last character matched at text[j].
+fn score_word(pattern: &[char], text: &[char]) -> Option<i64> {
+    if pattern.len() > text.len() {
+        return None;
+    }
+    let bonus = |j| {
+        MATCH
+            + if is_word_start(text, j) {
+                WORD_START
+            } else {
+                0
+            }
+    };
+    let mut best = text
+        .iter()
+        .enumerate()
+        .map(|(j, c)| (*c == pattern[0]).then(|| bonus(j)))
+        .collect::<Vec<_>>();
+    for p in &pattern[1..] {
+        let mut next = vec![None; text.len()];
+        // The best previous score, adjusted for the gap to j: best[k] + k.
+        let mut gapped: Option<i64> = None;
+        for j in 1..text.len() {
+            if j >= 2 {
+                if let Some(s) = best[j - 2] {
+                    let adjusted = s + (j as i64 - 2) * GAP;
+                    gapped = Some(gapped.map_or(adjusted, |g| g.max(adjusted)));
+                }
+            }
+            if text[j] != *p {
+                continue;
+            }
+            let consecutive = best[j - 1].map(|s| s + CONSECUTIVE);
+            let skipped = gapped.map(|g| g - (j as i64 - 1) * GAP);
+            next[j] = consecutive.max(skipped).map(|s| s + bonus(j));
+        }
+        best = next;
+    }
+    best.into_iter().flatten().max()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn word(pattern: &str, text: &str) -> Option<i64> {
+        score_word(&fold(pattern), &fold(text))
+    }
+
+    #[test]
+    fn words() {
+        assert_eq!(
+            word("mls", "Miles Davis"),
+            Some(3 * MATCH + WORD_START - 2 * GAP)
+        );
+        assert_eq!(
+            word("md", "Miles Davis"),
+            Some(2 * (MATCH + WORD_START) - 5 * GAP)
+        );
+        assert_eq!(
+            word("mil", "Miles"),
+            Some(3 * MATCH + WORD_START + 2 * CONSECUTIVE)
+        );
+        assert_eq!(word("sim", "Miles"), None);
+        assert!(word("blue", "Blue in Green") > word("blue", "Bill Evans: Quintessence"));
+    }
+
+    #[test]
+    fn ranking() {
+        let song = |file: &str, artist: &str, title: &str| Song {
+            file: file.into(),
+            artist: Some(artist.into()),
+            title: Some(title.into()),
+            ..Song::default()
+        };
+        let songs = vec![
+            song("a", "Miles Davis", "So What"),
+            song("b", "Bill Evans", "Blue in Green"),
+            song("c", "Miles Davis", "Blue in Green"),
+        ];
+        let tags = ["artist".to_string(), "title".to_string()];
+        let ranked = rank(
+            candidates(songs.clone(), &Kind::Songs, &tags),
+            "miles blue",
+            5,
+        );
+        let files = ranked.iter().map(|c| &*c.label).collect::<Vec<_>>();
+        assert_eq!(files, vec!["c"]);
+
+        let artists = rank(
+            candidates(songs, &Kind::Tag("artist".into()), &tags),
+            "mi",
+            5,
+        );
+        assert_eq!(artists.len(), 1);
+        assert_eq!(artists[0].label, "Miles Davis");
+        assert_eq!(artists[0].songs.len(), 2);
+    }
+}
@@ -0,0 +1,226 @@
+use crate::tags::native_tags;
+use mpdrs::lsinfo::LsInfoResponse;
+use mpdrs::Song;
+use std::fmt;
+
+pub enum Value {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Value>),
+    Object(Vec<(String, Value)>),
+}
+
+impl Value {
+    pub fn object<K: Into<String>>(fields: Vec<(K, Value)>) -> Value {
+        Value::Object(fields.into_iter().map(|(k, v)| (k.into(), v)).collect())
+    }
+
+    // Append the fields of another object to this one.
+    pub fn merge(self, other: Value) -> Value {
+        match other {
+            Value::Object(fields) => fields.into_iter().fold(self, |v, (k, f)| v.with(k, f)),
+            _ => self,
+        }
+    }
+
+    // Add a field to an object, any other value is returned unchanged.
+    pub fn with<K: Into<String>>(mut self, key: K, value: Value) -> Value {
+        if let Value::Object(fields) = &mut self {
+            fields.push((key.into(), value));
+        }
+        self
+    }
+}
+
+impl From<&str> for Value {
+    fn from(s: &str) -> Self {
+        Value::String(s.to_string())
+    }
+}
+
+impl From<String> for Value {
+    fn from(s: String) -> Self {
+        Value::String(s)
+    }
+}
+
+impl From<bool> for Value {
+    fn from(b: bool) -> Self {
+        Value::Bool(b)
+    }
+}
+
+impl From<u32> for Value {
+    fn from(n: u32) -> Self {
+        Value::Number(f64::from(n))
+    }
+}
+
+impl From<i64> for Value {
+    fn from(n: i64) -> Self {
+        Value::Number(n as f64)
+    }
+}
+
+impl From<f64> for Value {
+    fn from(n: f64) -> Self {
+        Value::Number(n)
+    }
+}
+
+impl<T: Into<Value>> From<Option<T>> for Value {
+    fn from(o: Option<T>) -> Self {
+        o.map_or(Value::Null, Into::into)
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Value::Null => write!(f, "null"),
+            Value::Bool(b) => write!(f, "{}", b),
+            Value::Number(n) if !n.is_finite() => write!(f, "null"),
+            Value::Number(n) if n.fract() == 0.0 => write!(f, "{:.0}", n),
+            Value::Number(n) => write!(f, "{}", n),
+            Value::String(s) => write_string(f, s),
+            Value::Array(vals) => {
+                write!(f, "[")?;
+                for (i, v) in vals.iter().enumerate() {
+                    if i != 0 {
+                        write!(f, ",")?;
+                    }
+                    write!(f, "{}", v)?;
+                }
+                write!(f, "]")
+            }
+            Value::Object(fields) => {
+                write!(f, "{{")?;
+                for (i, (k, v)) in fields.iter().enumerate() {
+                    if i != 0 {
+                        write!(f, ",")?;
+                    }
+                    write_string(f, k)?;
+                    write!(f, ":{}", v)?;
+                }
+                write!(f, "}}")
+            }
+        }
+    }
+}
+
+fn write_string(f: &mut fmt::Formatter, s: &str) -> fmt::Result {
+    write!(f, "\"")?;
+    for c in s.chars() {
+        match c {
+            '"' => write!(f, "\\\"")?,
+            '\\' => write!(f, "\\\\")?,
+            '\n' => write!(f, "\\n")?,
+            '\r' => write!(f, "\\r")?,
+            '\t' => write!(f, "\\t")?,
+            c if (c as u32) < 0x20 => write!(f, "\\u{:04x}", c as u32)?,
+            c => write!(f, "{}", c)?,
+        }
+    }
+    write!(f, "\"")
+}
+
+// All tags of a song, grouped by tag name. Every tag maps to an array, since
+// MPD allows most tags to occur more than once.
+pub fn tags(tags: &[(String, String)]) -> Value {
+    let mut grouped: Vec<(String, Vec<Value>)> = Vec::new();
+    for (k, v) in tags {
+        let key = k.to_lowercase();
+        match grouped.iter_mut().find(|(g, _)| *g == key) {
+            Some((_, vals)) => vals.push(v.as_str().into()),
+            None => grouped.push((key, vec![v.as_str().into()])),
+        }
+    }
+    Value::Object(
+        grouped
+            .into_iter()
+            .map(|(k, vals)| (k, Value::Array(vals)))
+            .collect(),
+    )
+}
+
+// MPD reports the duration and audio format of songs alongside the tags, but
+// they are not tags, and are presented as typed fields instead.
+const NON_TAGS: &[&str] = &["duration", "format"];
+
+pub fn song(song: &Song) -> Value {
+    let field = |name: &str| {
+        song.tags
+            .iter()
+            .find(|(k, _)| k.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    };
+    let duration = field("duration")
+        .and_then(|d| d.parse::<f64>().ok())
+        .or_else(|| song.duration.map(|d| d.as_secs_f64()));
+    let song_tags = native_tags(song)
+        .into_iter()
+        .filter(|(k, _)| !NON_TAGS.iter().any(|n| k.eq_ignore_ascii_case(n)))
+        .collect::<Vec<_>>();
+
+    Value::object(vec![
+        ("file", song.file.as_str().into()),
+        ("duration", duration.into()),
+        ("position", song.place.map(|p| p.pos + 1).into()),
+        ("id", song.place.map(|p| p.id).into()),
+        ("last_modified", song.last_mod.clone().into()),
+        ("format", field("format").into()),
+        ("tags", tags(&song_tags)),
+    ])
+}
+
+pub fn lsinfo(entry: &LsInfoResponse) -> Value {
+    let (kind, path, metadata) = match entry {
+        LsInfoResponse::Song(s) => {
+            return Value::object(vec![("type", "song".into())]).merge(song(s))
+        }
+        LsInfoResponse::Directory { path, metadata } => ("directory", path, metadata),
+        LsInfoResponse::Playlist { path, metadata } => ("playlist", path, metadata),
+    };
+    let last_modified = metadata
+        .iter()
+        .find(|(k, _)| k == "Last-Modified")
+        .map(|(_, v)| v.as_str());
+    Value::object(vec![
+        ("type", kind.into()),
+        ("path", path.as_str().into()),
+        ("last_modified", last_modified.into()),
+    ])
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn escaping() {
+        let v = Value::from("a \"quoted\"\\ \n\u{1} string");
+        assert_eq!(v.to_string(), r#""a \"quoted\"\\ \n\u0001 string""#);
+    }
+
+    #[test]
+    fn numbers() {
+        assert_eq!(Value::from(3u32).to_string(), "3");
+        assert_eq!(Value::from(2.5).to_string(), "2.5");
+        assert_eq!(Value::from(f64::NAN).to_string(), "null");
+    }
+
+    #[test]
+    fn multi_valued_tags() {
+        let t = vec![
+            ("Artist".to_string(), "a".to_string()),
+            ("Genre".to_string(), "g".to_string()),
+            ("Artist".to_string(), "b".to_string()),
+        ];
+        assert_eq!(
+            tags(&t).to_string(),
+            r#"{"artist":["a","b"],"genre":["g"]}"#
+        );
+    }
+}
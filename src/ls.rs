@@ -0,0 +1,352 @@
+use crate::ansi::{is_dumb_terminal, theme, FormattedString, Style};
+use crate::error::Error;
+use crate::raw;
+use crate::status::duration_format;
+use crate::table::{Align, Row, Table};
+use crate::tags::Tags;
+use mpdrs::lsinfo::LsInfoResponse;
+use std::str::FromStr;
+use std::time::Duration;
+
+#[derive(Default)]
+pub struct Options {
+    pub recursive: bool,
+    pub long: bool,
+    pub classify: bool,
+    pub sort: Option<SortKey>,
+    pub reverse: bool,
+}
+
+#[derive(Clone, Copy)]
+pub enum SortKey {
+    Name,
+    Mtime,
+    Duration,
+}
+
+impl FromStr for SortKey {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "name" => Ok(SortKey::Name),
+            "mtime" => Ok(SortKey::Mtime),
+            "duration" => Ok(SortKey::Duration),
+            _ => Err(format!(
+                "invalid sort key, expected name, mtime or duration: {}",
+                s
+            )),
+        }
+    }
+}
+
+// List the entries at path, recursively if requested. Recursive listings
+// only include metadata if detailed is set, as it makes the response much
+// larger.
+pub fn entries(
+    raw: &mut raw::Connection,
+    path: &str,
+    options: &Options,
+    detailed: bool,
+) -> Result<Vec<LsInfoResponse>, Error> {
+    let command = match (options.recursive, detailed || options.sort.is_some()) {
+        (false, _) => "lsinfo",
+        (true, false) => "listall",
+        (true, true) => "listallinfo",
+    };
+    let mut entries = raw.entries(command, &[path])?;
+    order(&mut entries, options);
+    Ok(entries)
+}
+
+// The entries at path in the entries of the cache, as listed by entries.
+pub fn cached(entries: Vec<LsInfoResponse>, path: &str, options: &Options) -> Vec<LsInfoResponse> {
+    let mut entries = entries
+        .into_iter()
+        .filter(|entry| {
+            let entry_path = entry_path(entry);
+            let relative = match path {
+                "" => Some(entry_path),
+                path => entry_path
+                    .strip_prefix(path)
+                    .and_then(|p| p.strip_prefix('/')),
+            };
+            // As lsinfo, a song is listed by its own path.
+            let song = matches!(entry, LsInfoResponse::Song(_)) && entry_path == path;
+            song || relative.is_some_and(|r| options.recursive || !r.contains('/'))
+        })
+        .collect::<Vec<_>>();
+    order(&mut entries, options);
+    entries
+}
+
+fn order(entries: &mut [LsInfoResponse], options: &Options) {
+    if let Some(key) = options.sort {
+        sort(entries, key);
+    }
+    if options.reverse {
+        entries.reverse();
+    }
+}
+
+pub fn print(entries: &[LsInfoResponse], path: &str, options: &Options) {
+    if options.long {
+        print_long(entries);
+    } else if options.recursive {
+        print!("{}", tree(entries, path, is_dumb_terminal()));
+    } else {
+        for entry in entries {
+            if options.classify {
+                println!("{}", name(entry, entry_path(entry)));
+            } else {
+                println!("{}", entry_path(entry));
+            }
+        }
+    }
+}
+
+fn sort(entries: &mut [LsInfoResponse], key: SortKey) {
+    match key {
+        SortKey::Name => entries.sort_by(|a, b| entry_path(a).cmp(entry_path(b))),
+        // Timestamps are in ISO 8601, which sorts chronologically as text.
+        SortKey::Mtime => entries.sort_by(|a, b| last_modified(a).cmp(&last_modified(b))),
+        SortKey::Duration => entries.sort_by_key(duration),
+    }
+}
+
+fn entry_path(entry: &LsInfoResponse) -> &str {
+    match entry {
+        LsInfoResponse::Song(song) => &song.file,
+        LsInfoResponse::Directory { path, .. } | LsInfoResponse::Playlist { path, .. } => path,
+    }
+}
+
+fn last_modified(entry: &LsInfoResponse) -> Option<&str> {
+    match entry {
+        LsInfoResponse::Song(song) => song.last_mod.as_deref(),
+        LsInfoResponse::Directory { metadata, .. } | LsInfoResponse::Playlist { metadata, .. } => {
+            metadata
+                .iter()
+                .find(|(k, _)| k == "Last-Modified")
+                .map(|(_, v)| v.as_str())
+        }
+    }
+}
+
+fn duration(entry: &LsInfoResponse) -> Option<Duration> {
+    match entry {
+        LsInfoResponse::Song(song) => song.duration.or_else(|| {
+            Tags::from_song(song)
+                .get_option_joined("duration")
+                .and_then(|d| d.parse().ok())
+                .map(Duration::from_secs_f64)
+        }),
+        _ => None,
+    }
+}
+
+// The name of an entry, styled by its type, with a marker for directories
+// and playlists like ls -F.
+fn name(entry: &LsInfoResponse, name: &str) -> String {
+    let (marker, style) = match entry {
+        LsInfoResponse::Song(_) => ("", Style::default()),
+        LsInfoResponse::Directory { .. } => ("/", theme().directory),
+        LsInfoResponse::Playlist { .. } => ("@", theme().playlist),
+    };
+    format!("{}{}", FormattedString::new(name).style(style), marker)
+}
+
+fn type_char(entry: &LsInfoResponse) -> &'static str {
+    match entry {
+        LsInfoResponse::Song(_) => "-",
+        LsInfoResponse::Directory { .. } => "d",
+        LsInfoResponse::Playlist { .. } => "p",
+    }
+}
+
+// Modification time such as 2020-01-01T12:00:00Z, shown as 2020-01-01 12:00.
+fn mtime_format(mtime: &str) -> String {
+    mtime.get(..16).unwrap_or(mtime).replacen('T', " ", 1)
+}
+
+// One line per entry with its type, modification time, duration, audio
+// format, title and path.
+fn print_long(entries: &[LsInfoResponse]) {
+    let fields = entries
+        .iter()
+        .map(|entry| {
+            let (format, title) = match entry {
+                LsInfoResponse::Song(song) => {
+                    let tags = Tags::from_song(song);
+                    (
+                        tags.get_option_joined("format").unwrap_or_default(),
+                        song.title.clone().unwrap_or_default(),
+                    )
+                }
+                _ => Default::default(),
+            };
+            (
+                type_char(entry),
+                last_modified(entry).map(mtime_format).unwrap_or_default(),
+                duration(entry)
+                    .map(|d| duration_format(&d))
+                    .unwrap_or_default(),
+                format,
+                title,
+                name(entry, entry_path(entry)),
+            )
+        })
+        .collect::<Vec<_>>();
+    let rows = fields
+        .iter()
+        .map(|(kind, mtime, duration, format, title, name)| {
+            Row::new(vec![
+                FormattedString::new(kind),
+                FormattedString::new(mtime),
+                FormattedString::new(duration),
+                FormattedString::new(format),
+                FormattedString::new(title),
+                FormattedString::new(name),
+            ])
+        })
+        .collect::<Vec<_>>();
+    if !rows.is_empty() {
+        println!("{}", Table::new(&rows).align(2, Align::Right));
+    }
+}
+
+struct Node<'a> {
+    name: &'a str,
+    entry: Option<&'a LsInfoResponse>,
+    children: Vec<Node<'a>>,
+}
+
+impl<'a> Node<'a> {
+    fn insert(&mut self, components: &[&'a str], entry: &'a LsInfoResponse) {
+        let Some((first, rest)) = components.split_first() else {
+            return;
+        };
+        let index = match self.children.iter().position(|c| c.name == *first) {
+            Some(index) => index,
+            None => {
+                self.children.push(Node {
+                    name: first,
+                    entry: None,
+                    children: vec![],
+                });
+                self.children.len() - 1
+            }
+        };
+        let child = &mut self.children[index];
+        if rest.is_empty() {
+            child.entry = Some(entry);
+        } else {
+            child.insert(rest, entry);
+        }
+    }
+
+    fn render(&self, prefix: &str, ascii: bool, out: &mut String) {
+        let (branch, last_branch, line) = if ascii {
+            ("|-- ", "`-- ", "|   ")
+        } else {
+            ("├── ", "└── ", "│   ")
+        };
+        for (i, child) in self.children.iter().enumerate() {
+            let last = i + 1 == self.children.len();
+            let name = match child.entry {
+                Some(entry) => name(entry, child.name),
+                // Parents of entries that were not listed themselves.
+                None => format!("{}/", child.name),
+            };
+            out.push_str(&format!(
+                "{}{}{}\n",
+                prefix,
+                if last { last_branch } else { branch },
+                name
+            ));
+            let prefix = format!("{}{}", prefix, if last { "    " } else { line });
+            child.render(&prefix, ascii, out);
+        }
+    }
+}
+
+// Entries below root drawn as a tree, in the order in which they were listed.
+// Lines are drawn with ASCII characters if ascii is set.
+fn tree(entries: &[LsInfoResponse], root: &str, ascii: bool) -> String {
+    let mut top = Node {
+        name: root,
+        entry: None,
+        children: vec![],
+    };
+    for entry in entries {
+        let path = entry_path(entry);
+        let relative = match root {
+            "" => path,
+            root => match path.strip_prefix(root).and_then(|p| p.strip_prefix('/')) {
+                Some(relative) => relative,
+                None => continue,
+            },
+        };
+        top.insert(&relative.split('/').collect::<Vec<_>>(), entry);
+    }
+    let mut out = format!(
+        "{}\n",
+        FormattedString::new(if root.is_empty() { "." } else { root }).style(theme().directory)
+    );
+    top.render("", ascii, &mut out);
+    out
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use mpdrs::Song;
+
+    fn dir(path: &str) -> LsInfoResponse {
+        LsInfoResponse::Directory {
+            path: path.into(),
+            metadata: vec![],
+        }
+    }
+
+    fn song(file: &str, seconds: u64) -> LsInfoResponse {
+        LsInfoResponse::Song(Song {
+            file: file.into(),
+            duration: Some(Duration::from_secs(seconds)),
+            ..Song::default()
+        })
+    }
+
+    #[test]
+    fn trees() {
+        let entries = vec![
+            dir("a/b"),
+            song("a/b/1.flac", 1),
+            song("a/b/2.flac", 2),
+            dir("a/b/c"),
+            song("a/b/c/3.flac", 3),
+            song("a/b/4.flac", 4),
+        ];
+        assert_eq!(
+            tree(&entries, "a", true),
+            "a\n\
+             `-- b/\n    \
+                 |-- 1.flac\n    \
+                 |-- 2.flac\n    \
+                 |-- c/\n    \
+                 |   `-- 3.flac\n    \
+                 `-- 4.flac\n"
+        );
+    }
+
+    #[test]
+    fn sorting() {
+        let mut entries = vec![song("b", 3), dir("c"), song("a", 1)];
+        sort(&mut entries, SortKey::Name);
+        let paths = entries.iter().map(entry_path).collect::<Vec<_>>();
+        assert_eq!(paths, vec!["a", "b", "c"]);
+        sort(&mut entries, SortKey::Duration);
+        let paths = entries.iter().map(entry_path).collect::<Vec<_>>();
+        assert_eq!(paths, vec!["c", "a", "b"]);
+    }
+}
@@ -1,24 +1,50 @@
-use mpdrs::lsinfo::LsInfoResponse;
 use mpdrs::Client;
 use mpdrs::Song;
+use std::io::Read;
 use std::net::TcpStream;
 use std::process::Command;
 use std::sync::atomic::Ordering;
 
+mod album;
+mod album_info;
 mod albumart;
 mod ansi;
+mod autodj;
+mod bar;
+mod browse;
+mod cache;
 mod cli;
 mod config;
 mod error;
+mod filter;
+mod follow;
+mod format;
+mod fuzzy;
+mod json;
 mod logger;
+mod ls;
 mod now_playing;
+mod pick;
+mod playlist_file;
+mod progress;
+mod query;
 mod queue;
+mod random;
+mod raw;
+mod records;
+mod related;
 mod seek;
+mod smart;
+mod sort;
+mod stats;
 mod status;
+mod sticker;
 mod subcommands;
 mod tab;
 mod table;
 mod tags;
+mod unicode;
+mod update;
 
 use cli::SubCommand;
 use error::{Error, WithContext};
@@ -50,70 +76,243 @@ fn try_main() -> Result<(), Error> {
     let mpd_host_str = format!("{}:6600", &mpd_host);
 
     ansi::PLAIN_FORMATTING.store(opts.plain_formatting, Ordering::Relaxed);
+    ansi::set_theme(conf.theme.clone());
 
-    let mut c = Client::new(TcpStream::connect(&mpd_host_str).context("connecting to MPD")?)?;
+    if let Some(SubCommand::Hosts) = opts.subcommand {
+        print_hosts(&conf, opts.json);
+        return Ok(());
+    }
+
+    if let Some(SubCommand::SmartList) = opts.subcommand {
+        smart::list(&conf, opts.json);
+        return Ok(());
+    }
+
+    if opts.json && follows(opts.subcommand.as_ref()) {
+        return Err(Error::ArgParse(
+            "--follow can not be combined with --json".into(),
+        ));
+    }
+
+    let stream = match TcpStream::connect(&mpd_host_str) {
+        Ok(stream) => stream,
+        Err(e) => {
+            let subcommand = opts.subcommand.expect("no subcommand, this is a bug.");
+            // Commands which only read the cache work without MPD.
+            if works_offline(&subcommand) {
+                if let Some(cache) = cache::read(&mpd_host)? {
+                    log::trace!("Failed to connect to MPD, using the cache: {}", e);
+                    return run_cached(subcommand, &cache, None, None, &conf, opts.json);
+                }
+            }
+            return Err(e).context("connecting to MPD");
+        }
+    };
+    let mut raw = raw::Connection::new(stream.try_clone().context("connecting to MPD")?);
+    let mut c = Client::new(stream)?;
 
     match opts.subcommand.expect("no subcommand, this is a bug.") {
-        SubCommand::Current => now_playing::now_playing(&mut c, &conf)?,
+        SubCommand::Current { format, follow } if follow.enabled => {
+            let template = parse_template(&conf, format.as_deref())?;
+            let mut view = now_playing::View::new(&conf, template.as_ref());
+            follow::follow(&mut c, &mut raw, &mut view, follow.title)?;
+        }
+        SubCommand::Current { format, .. } => {
+            let template = parse_template(&conf, format.as_deref())?;
+            now_playing::now_playing(&mut c, &conf, opts.json, template.as_ref())?;
+        }
+        SubCommand::Info { targets, format } => {
+            let template = parse_template(&conf, format.as_deref())?;
+            let template = template.as_ref();
+            now_playing::info(&mut c, &mut raw, &conf, &targets, opts.json, template)?;
+        }
         SubCommand::Play { position: Some(id) } => c.play_from_position(id.get() - 1)?,
         SubCommand::Play { position: None } => c.play()?,
         SubCommand::Pause => c.pause(true)?,
         SubCommand::Toggle => c.toggle_pause()?,
-        SubCommand::Ls { path } => {
-            let path = path.as_ref().map_or("", |s| trim_path(&*s));
-            for entry in c.lsinfo(path)? {
-                match entry {
-                    LsInfoResponse::Song(Song { file, .. }) => println!("{}", file),
-                    LsInfoResponse::Directory { path, .. }
-                    | LsInfoResponse::Playlist { path, .. } => println!("{}", path),
-                }
-            }
+        SubCommand::Ls {
+            path,
+            records,
+            options,
+        } => {
+            let path = path.as_ref().map_or("", |s| trim_path(s));
+            let detailed = opts.json || options.long || records.fields.is_some();
+            let entries = ls::entries(&mut raw, path, &options, detailed)?;
+            print_entries(&entries, path, &options, &records, opts.json);
         }
+        // The cache is only used for completion offline, since a single
+        // lsinfo is cheaper than loading the cache, and includes playlists.
+        SubCommand::Tab { path, null } => {
+            tab::complete(
+                &c.lsinfo(tab::directory(&path))?,
+                &path,
+                &records::Records { null, fields: None },
+            );
+        }
+        SubCommand::Browse { hierarchy } => browse::browse(&mut c, &mut raw, hierarchy)?,
         SubCommand::Clear => c.clear()?,
         SubCommand::Next => c.next()?,
         SubCommand::Prev => c.prev()?,
         SubCommand::Stop => c.stop()?,
-        SubCommand::Add { path } => c.add(&*trim_path(&*path))?,
-        SubCommand::Load { path } => c.load(&*path, ..)?,
-        SubCommand::Queue => queue::print(c.queue()?, &c.currentsong()?),
-        SubCommand::Search { query } => {
-            for song in c.search(&query.to_mpd_query(), None)? {
-                println!("{}", song.file);
-            }
+        SubCommand::Add { path, null } if path == "-" => add_from_stdin(&mut raw, null)?,
+        SubCommand::Add { path, .. } => c.add(trim_path(&path))?,
+        SubCommand::Load { path } => c.load(&path, ..)?,
+        SubCommand::Queue { format, follow } if follow.enabled => {
+            let template = parse_template(&conf, format.as_deref())?;
+            let mut view = queue::View::new(template.as_ref());
+            follow::follow(&mut c, &mut raw, &mut view, follow.title)?;
+        }
+        SubCommand::Queue { .. } if opts.json => {
+            let songs = c.queue()?.iter().map(json::song).collect();
+            println!("{}", json::Value::Array(songs));
         }
-        SubCommand::List { tag, query } => {
-            for val in c.list(&mpdrs::Term::Tag(&*tag), &query.to_mpd_query())? {
-                println!("{}", val);
+        SubCommand::Queue {
+            format: Some(format),
+            ..
+        } => {
+            let template = format::Template::from_config_or_str(&conf, &format)?;
+            let queue = c.queue()?;
+            print_songs(Some(&mut c), &queue, &template)?;
+        }
+        SubCommand::Queue { format: None, .. } => queue::print(c.queue()?, &c.currentsong()?),
+        SubCommand::QueueExport { format, file } => playlist_file::export(
+            &c.queue()?,
+            format,
+            file.as_deref(),
+            conf.music_directory.as_deref(),
+        )?,
+        SubCommand::QueueImport { file } => {
+            playlist_file::import(&mut c, &file, conf.music_directory.as_deref())?;
+        }
+        subcommand @ (SubCommand::Search { cached: true, .. }
+        | SubCommand::List { cached: true, .. }
+        | SubCommand::FuzzySearch { .. }) => {
+            let cache = cache::load(&mut c, &mut raw, &mpd_host)?;
+            run_cached(
+                subcommand,
+                &cache,
+                Some(&mut c),
+                Some(&mut raw),
+                &conf,
+                opts.json,
+            )?;
+        }
+        SubCommand::Search {
+            query,
+            exact,
+            rating,
+            add,
+            format,
+            records,
+            options,
+            ..
+        } => {
+            let mut songs = search(&mut raw, exact, &query, &options)?;
+            if let Some(rating) = rating {
+                songs = sticker::rated(&mut c, songs, rating)?;
+            }
+            if add {
+                add_songs(&mut raw, &songs)?;
+                return Ok(());
+            }
+            if records.is_set() && !opts.json {
+                records.print_songs(&songs);
+                return Ok(());
             }
+            print_song_list(Some(&mut c), &conf, &songs, opts.json, format.as_deref())?;
+        }
+        SubCommand::PlaylistShow { name, format } => {
+            let songs = c.playlist(&name)?;
+            print_song_list(Some(&mut c), &conf, &songs, opts.json, format.as_deref())?;
+        }
+        SubCommand::SmartList => unreachable!("smart list is handled before connecting to MPD"),
+        SubCommand::SmartShow { name, format } => {
+            let songs = smart::songs(&mut raw, smart::find(&conf, &name)?)?;
+            print_song_list(Some(&mut c), &conf, &songs, opts.json, format.as_deref())?;
+        }
+        SubCommand::SmartLoad { name } => {
+            let songs = smart::songs(&mut raw, smart::find(&conf, &name)?)?;
+            add_songs(&mut raw, &songs)?;
+        }
+        SubCommand::SmartSave { name } => smart::save(&mut raw, smart::find(&conf, &name)?)?,
+        SubCommand::List {
+            tag, query, null, ..
+        } => {
+            let vals = c.list(&mpdrs::Term::Tag(&tag), &query.to_mpd_query())?;
+            print_values(&vals, null, opts.json);
         }
         SubCommand::ReadComments { file } => {
             let table_rows = c
-                .readcomments(&*trim_path(&*file))?
+                .readcomments(trim_path(&file))?
                 .collect::<Result<Vec<_>, _>>()?;
+            if opts.json {
+                println!("{}", json::tags(&table_rows));
+                return Ok(());
+            }
             let table_rows = table_rows
                 .iter()
                 .map(|(k, v)| {
                     table::Row::new(vec![
-                        ansi::FormattedString::new(k).style(ansi::Style::Bold),
-                        ansi::FormattedString::new(v),
+                        ansi::FormattedString::new(k).style(ansi::theme().tag_label),
+                        ansi::FormattedString::new(v).style(ansi::theme().tag_value),
                     ])
                 })
                 .collect::<Vec<_>>();
-            println!("{}", table::Table { rows: &*table_rows });
+            println!("{}", table::Table::new(&table_rows));
+        }
+        SubCommand::Update { path, rescan, wait } => {
+            let path = path.as_deref().map(trim_path);
+            update::update(&mut c, &mut raw, path, rescan, wait, opts.json)?;
         }
-        SubCommand::Update => {
-            c.update()?;
+        SubCommand::Bar { style } => bar::run(&mut c, &conf, style)?,
+        SubCommand::Hosts => unreachable!("hosts is handled before connecting to MPD"),
+        SubCommand::Album { group, mode } => {
+            album::album(&mut c, &mut raw, group, mode, opts.json)?
         }
-        SubCommand::Status => status::status(&mut c)?,
+        SubCommand::Sticker { action } => sticker::sticker(&mut c, &mut raw, action, opts.json)?,
+        SubCommand::Rate { rating } => sticker::rate(&mut c, rating)?,
+        SubCommand::Autodj { query, options } => {
+            autodj::autodj(&mut c, &mut raw, &query, &options)?
+        }
+        SubCommand::Random {
+            query,
+            albums,
+            n,
+            print,
+            play,
+            format,
+        } => {
+            let songs = if albums {
+                pick::album(&mut raw, &query)?
+            } else {
+                pick::tracks(&mut raw, &query, n)?
+            };
+            if print {
+                print_song_list(Some(&mut c), &conf, &songs, opts.json, format.as_deref())?;
+            } else {
+                pick::enqueue(&mut c, &mut raw, &songs, play)?;
+            }
+        }
+        SubCommand::Related { add } => related::related(&mut c, &mut raw, add, opts.json)?,
+        SubCommand::Stats => stats::stats(&mut c, opts.json)?,
+        SubCommand::Count { query, group } => {
+            stats::count(&mut raw, &query, group.as_deref(), opts.json)?;
+        }
+        SubCommand::Status { follow } if follow.enabled => {
+            follow::follow(&mut c, &mut raw, &mut status::View::default(), follow.title)?;
+        }
+        SubCommand::Status { .. } => status::status(&mut c, opts.json)?,
         SubCommand::Albumart { song_path, output } => {
-            albumart::fetch(&mut c, song_path.as_deref(), &*output)?;
+            albumart::fetch(&mut c, song_path.as_deref(), &output)?;
+        }
+        SubCommand::AlbumInfo { song_path } => {
+            album_info::album_info(&mut c, &mut raw, &conf, song_path.as_deref(), opts.json)?;
         }
         SubCommand::Mv { from, to } => {
             c.move_range(from.get() - 1..from.get(), to.get() - 1)?;
         }
         SubCommand::Del { index } => c.delete(index.get() - 1..index.get())?,
         SubCommand::Seek { position } => seek::seek(&mut c, position)?,
-        SubCommand::Tab { path } => tab::complete(&mut c, &*path)?,
         SubCommand::Custom(args) => {
             log::trace!("Spawning process for custom subcommand: {:?}", args);
             Command::new(&args[0])
@@ -128,13 +327,284 @@ fn try_main() -> Result<(), Error> {
     Ok(())
 }
 
+fn works_offline(subcommand: &SubCommand) -> bool {
+    matches!(
+        subcommand,
+        SubCommand::Ls { .. }
+            | SubCommand::Tab { .. }
+            | SubCommand::Search {
+                cached: true,
+                rating: None,
+                add: false,
+                ..
+            }
+            | SubCommand::List { cached: true, .. }
+            | SubCommand::FuzzySearch { add: false, .. }
+    )
+}
+
+// Run a command which reads the cache instead of querying MPD. Without a
+// connection to MPD, nothing can be added to the queue, and templates can
+// not use raw comments.
+fn run_cached(
+    subcommand: SubCommand,
+    cache: &cache::Cache,
+    mut c: Option<&mut Client>,
+    raw: Option<&mut raw::Connection>,
+    conf: &config::Config,
+    json: bool,
+) -> Result<(), Error> {
+    let raw = || raw.expect("adding songs requires a connection to MPD");
+    match subcommand {
+        SubCommand::Ls {
+            path,
+            records,
+            options,
+        } => {
+            let path = path.as_ref().map_or("", |s| trim_path(s));
+            let entries = ls::cached(cache.entries(), path, &options);
+            print_entries(&entries, path, &options, &records, json);
+        }
+        SubCommand::Tab { path, null } => {
+            tab::complete(
+                &cache.entries(),
+                &path,
+                &records::Records { null, fields: None },
+            );
+        }
+        SubCommand::Search {
+            query,
+            exact,
+            rating,
+            add,
+            format,
+            records,
+            options,
+            ..
+        } => {
+            let mut songs = cache.search(&query, exact, &options)?;
+            if let Some(rating) = rating {
+                let c = c
+                    .as_deref_mut()
+                    .expect("filtering by rating requires a connection to MPD");
+                songs = sticker::rated(c, songs, rating)?;
+            }
+            if add {
+                return add_songs(raw(), &songs);
+            }
+            if records.is_set() && !json {
+                records.print_songs(&songs);
+                return Ok(());
+            }
+            print_song_list(c, conf, &songs, json, format.as_deref())?;
+        }
+        SubCommand::List {
+            tag, query, null, ..
+        } => print_values(&cache.list(&tag, &query)?, null, json),
+        SubCommand::FuzzySearch {
+            text,
+            kind,
+            limit,
+            add,
+            format,
+            records,
+        } => {
+            let candidates = fuzzy::candidates(cache.songs(), &kind, &conf.fuzzy.tags);
+            let mut ranked = fuzzy::rank(candidates, &text, limit.unwrap_or(conf.fuzzy.limit));
+            for candidate in &mut ranked {
+                sort::natural(&mut candidate.songs);
+            }
+            if add {
+                let songs = ranked.into_iter().flat_map(|c| c.songs).collect::<Vec<_>>();
+                add_songs(raw(), &songs)?;
+            } else if let fuzzy::Kind::Songs = kind {
+                let songs = ranked.into_iter().flat_map(|c| c.songs).collect::<Vec<_>>();
+                if records.is_set() && !json {
+                    records.print_songs(&songs);
+                    return Ok(());
+                }
+                print_song_list(c, conf, &songs, json, format.as_deref())?;
+            } else {
+                let labels = ranked.into_iter().map(|c| c.label).collect::<Vec<_>>();
+                print_values(&labels, records.null, json);
+            }
+        }
+        _ => unreachable!("only commands which read the cache are run with it"),
+    }
+    Ok(())
+}
+
+fn print_entries(
+    entries: &[mpdrs::lsinfo::LsInfoResponse],
+    path: &str,
+    options: &ls::Options,
+    records: &records::Records,
+    json: bool,
+) {
+    if json {
+        let entries = entries.iter().map(json::lsinfo).collect();
+        println!("{}", json::Value::Array(entries));
+    } else if records.is_set() {
+        records.print_entries(entries);
+    } else {
+        ls::print(entries, path, options);
+    }
+}
+
+fn print_values(values: &[String], null: bool, json: bool) {
+    if json {
+        let values = values
+            .iter()
+            .map(|v| json::Value::from(v.as_str()))
+            .collect();
+        println!("{}", json::Value::Array(values));
+    } else {
+        records::Records { null, fields: None }.print_values(values);
+    }
+}
+
+fn follows(subcommand: Option<&SubCommand>) -> bool {
+    match subcommand {
+        Some(SubCommand::Current { follow, .. })
+        | Some(SubCommand::Queue { follow, .. })
+        | Some(SubCommand::Status { follow }) => follow.enabled,
+        _ => false,
+    }
+}
+
+// Search with find or search, sorted by MPD if a sort tag is given, and in
+// natural album order otherwise.
+fn search(
+    raw: &mut raw::Connection,
+    exact: bool,
+    query: &cli::SearchQuery,
+    options: &cli::SearchOptions,
+) -> Result<Vec<Song>, Error> {
+    let options_args = options.to_args();
+    let mut args = query.to_args();
+    args.extend(options_args.iter().map(String::as_str));
+    let mut songs = raw.songs(if exact { "find" } else { "search" }, &args)?;
+    if options.sort.is_none() {
+        sort::natural(&mut songs);
+    }
+    Ok(songs)
+}
+
+fn parse_template(
+    conf: &config::Config,
+    format: Option<&str>,
+) -> Result<Option<format::Template>, Error> {
+    format
+        .map(|f| format::Template::from_config_or_str(conf, f))
+        .transpose()
+}
+
+// Print songs as JSON, with a template, or just the file names.
+fn print_song_list(
+    c: Option<&mut Client>,
+    conf: &config::Config,
+    songs: &[Song],
+    json: bool,
+    format: Option<&str>,
+) -> Result<(), Error> {
+    if json {
+        let songs = songs.iter().map(json::song).collect();
+        println!("{}", json::Value::Array(songs));
+    } else if let Some(template) = parse_template(conf, format)? {
+        print_songs(c, songs, &template)?;
+    } else {
+        for song in songs {
+            println!("{}", song.file);
+        }
+    }
+    Ok(())
+}
+
+// Print songs with a template. Without a connection to MPD, only the tags
+// known to MPD are available.
+fn print_songs(
+    mut c: Option<&mut Client>,
+    songs: &[Song],
+    template: &format::Template,
+) -> Result<(), Error> {
+    for song in songs {
+        let tags = match c.as_deref_mut() {
+            Some(c) => template.tags_for(c, song)?,
+            None => tags::Tags::from_song(song),
+        };
+        println!("{}", template.render(song, &tags));
+    }
+    Ok(())
+}
+
+fn add_songs(raw: &mut raw::Connection, songs: &[Song]) -> Result<(), Error> {
+    let commands = songs
+        .iter()
+        .map(|song| ("add", vec![&*song.file]))
+        .collect::<Vec<_>>();
+    if !commands.is_empty() {
+        raw.command_list(&commands)?;
+    }
+    Ok(())
+}
+
+// Add the paths read from stdin, one per line or separated by NUL bytes, in a
+// single command list.
+fn add_from_stdin(raw: &mut raw::Connection, null: bool) -> Result<(), Error> {
+    let mut input = String::new();
+    std::io::stdin()
+        .read_to_string(&mut input)
+        .context("reading paths from stdin")?;
+    let separator = if null { '\0' } else { '\n' };
+    let commands = input
+        .split(separator)
+        .filter(|p| !p.is_empty())
+        .map(|p| ("add", vec![trim_path(p)]))
+        .collect::<Vec<_>>();
+    if !commands.is_empty() {
+        raw.command_list(&commands)?;
+    }
+    Ok(())
+}
+
+fn print_hosts(conf: &config::Config, json: bool) {
+    if json {
+        let hosts = conf
+            .hosts
+            .iter()
+            .map(|h| {
+                json::Value::object(vec![
+                    ("label", h.label.as_str().into()),
+                    ("host", h.host.as_str().into()),
+                ])
+            })
+            .collect();
+        println!("{}", json::Value::Array(hosts));
+        return;
+    }
+
+    let table_rows = conf
+        .hosts
+        .iter()
+        .map(|h| {
+            table::Row::new(vec![
+                ansi::FormattedString::new(&h.label).style(ansi::theme().tag_label),
+                ansi::FormattedString::new(&h.host).style(ansi::theme().tag_value),
+            ])
+        })
+        .collect::<Vec<_>>();
+    if !table_rows.is_empty() {
+        println!("{}", table::Table::new(&table_rows));
+    }
+}
+
 fn mpd_host(opts: &cli::Opts, conf: &config::Config) -> String {
     if let Some(host) = config::mpd_host_env_var() {
         log::trace!("Found MPD_HOST environment variable: {}", host);
-        lookup_mpd_host(&*host, conf)
+        lookup_mpd_host(&host, conf)
     } else if let Some(host) = &opts.host {
         log::trace!("Found MPD host in command line arguments: {}", host);
-        lookup_mpd_host(&*host, conf)
+        lookup_mpd_host(host, conf)
     } else {
         conf.default_mpd_host()
     }
@@ -1,48 +1,224 @@
-use crate::ansi::{FormattedString, Style};
+use crate::ansi::{theme, FormattedString};
 use crate::config::{Config, Tag};
 use crate::error::Error;
+use crate::follow::{self, View as _};
+use crate::format::Template;
+use crate::json;
+use crate::progress::Progress;
+use crate::raw;
+use crate::sticker;
 use crate::table::{Row, Table};
 use crate::tags::Tags;
+use mpdrs::error::{Error as MpdError, ErrorCode};
 use mpdrs::Song;
+use std::num::NonZeroU32;
+use std::time::Duration;
 
-pub fn now_playing(client: &mut mpdrs::Client, conf: &Config) -> Result<(), Error> {
-    let song = match client.currentsong()? {
-        None => {
-            println!("Not playing.");
-            return Ok(());
+pub fn now_playing(
+    client: &mut mpdrs::Client,
+    conf: &Config,
+    json: bool,
+    template: Option<&Template>,
+) -> Result<(), Error> {
+    let mut view = View::new(conf, template);
+    view.update(client)?;
+
+    if json {
+        println!("{}", view.json());
+        return Ok(());
+    }
+
+    print!("{}", view.render(Duration::ZERO));
+    Ok(())
+}
+
+// A song to show with info.
+pub enum Target {
+    Path(String),
+    // A position in the queue, counting from 1.
+    Position(NonZeroU32),
+    Next,
+}
+
+// Show the view of current for every target, without a progress bar. Targets
+// which are not found are printed as null with --json.
+pub fn info(
+    client: &mut mpdrs::Client,
+    raw: &mut raw::Connection,
+    conf: &Config,
+    targets: &[Target],
+    json: bool,
+    template: Option<&Template>,
+) -> Result<(), Error> {
+    let mut values = vec![];
+    for (i, target) in targets.iter().enumerate() {
+        let song = match target {
+            Target::Path(path) => raw.songs("find", &["file", path])?.into_iter().next(),
+            Target::Position(position) => queued(raw, position.get() - 1)?,
+            Target::Next => match client.status()?.nextsong {
+                Some(next) => queued(raw, next.pos)?,
+                None => None,
+            },
+        };
+        let mut view = View::new(conf, template);
+        view.load(client, song)?;
+        if json {
+            values.push(view.json());
+            continue;
+        }
+
+        if i > 0 && template.is_none() {
+            println!();
+        }
+        match (&view.song, target) {
+            (Some(_), _) => print!("{}", view.render(Duration::ZERO)),
+            (None, Target::Path(path)) => println!("{} is not a song in the database.", path),
+            (None, Target::Position(position)) => {
+                println!("There is no song at position {} in the queue.", position)
+            }
+            (None, Target::Next) => println!("There is no next song."),
+        }
+    }
+    if json {
+        println!("{}", json::Value::Array(values));
+    }
+    Ok(())
+}
+
+// The song at a position in the queue, counting from 0.
+fn queued(raw: &mut raw::Connection, position: u32) -> Result<Option<Song>, Error> {
+    match raw.songs("playlistinfo", &[&position.to_string()]) {
+        Ok(songs) => Ok(songs.into_iter().next()),
+        // MPD rejects positions past the end of the queue.
+        Err(Error::Mpd(MpdError::Server(e))) if e.code == ErrorCode::Argument => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+pub struct View<'a> {
+    conf: &'a Config,
+    template: Option<&'a Template>,
+    song: Option<Song>,
+    raw_comments: Vec<(String, String)>,
+    stickers: Vec<(String, String)>,
+    status: mpdrs::Status,
+}
+
+impl<'a> View<'a> {
+    pub fn new(conf: &'a Config, template: Option<&'a Template>) -> View<'a> {
+        View {
+            conf,
+            template,
+            song: None,
+            raw_comments: vec![],
+            stickers: vec![],
+            status: mpdrs::Status::default(),
+        }
+    }
+
+    // Show song, reading its raw comments and stickers.
+    fn load(&mut self, client: &mut mpdrs::Client, song: Option<Song>) -> Result<(), Error> {
+        self.raw_comments = match &song {
+            Some(song) => client
+                .readcomments(&song.file)?
+                .collect::<Result<Vec<_>, _>>()?,
+            None => vec![],
+        };
+        self.stickers = match &song {
+            Some(song) => sticker::stickers(client, &song.file)?,
+            None => vec![],
+        };
+        self.song = song;
+        Ok(())
+    }
+
+    fn json(&self) -> json::Value {
+        match &self.song {
+            Some(song) => {
+                let stickers = self
+                    .stickers
+                    .iter()
+                    .map(|(name, value)| (name.as_str(), value.as_str().into()))
+                    .collect();
+                json::song(song)
+                    .with("comments", json::tags(&self.raw_comments))
+                    .with("stickers", json::Value::object(stickers))
+            }
+            None => json::Value::Null,
         }
-        Some(s) => s,
-    };
-
-    let tags = Tags::from_song_and_raw_comments(
-        &song,
-        client
-            .readcomments(&*song.file)?
-            .collect::<Result<_, _>>()?,
-    );
-
-    let table_rows = conf
-        .tags
-        .iter()
-        .map(|Tag { tag, label }| {
-            tags.get(&*tag)
+    }
+}
+
+impl<'a> follow::View for View<'a> {
+    fn subsystems(&self) -> &'static [&'static str] {
+        &["player", "sticker"]
+    }
+
+    fn update(&mut self, client: &mut mpdrs::Client) -> Result<(), Error> {
+        let song = client.currentsong()?;
+        self.load(client, song)?;
+        self.status = client.status()?;
+        Ok(())
+    }
+
+    fn render(&self, since_update: Duration) -> String {
+        let song = match &self.song {
+            Some(song) => song,
+            None => return "Not playing.\n".to_string(),
+        };
+        let tags = Tags::from_song_and_raw_comments(song, self.raw_comments.clone());
+
+        if let Some(template) = self.template {
+            return format!("{}\n", template.render(song, &tags));
+        }
+
+        let sticker_rows = self.conf.stickers.iter().map(|Tag { tag, label }| {
+            self.stickers
                 .iter()
-                .map(|value| {
-                    Row::new(vec![
-                        FormattedString::new(&*label.as_ref().unwrap_or(tag)).style(Style::Bold),
-                        FormattedString::new(*value),
-                    ])
-                })
+                .filter(|(name, _)| name == tag)
+                .map(|(_, value)| row(label.as_ref().unwrap_or(tag), value))
                 .collect::<Vec<_>>()
-        })
-        .flat_map(IntoIterator::into_iter)
-        .collect::<Vec<_>>();
+        });
+        let table_rows = self
+            .conf
+            .tags
+            .iter()
+            .map(|Tag { tag, label }| {
+                tags.get(tag)
+                    .iter()
+                    .map(|value| row(label.as_ref().unwrap_or(tag), value))
+                    .collect::<Vec<_>>()
+            })
+            .chain(sticker_rows)
+            .flat_map(IntoIterator::into_iter)
+            .collect::<Vec<_>>();
 
-    println!("{}", header(&song, &tags));
-    if !table_rows.is_empty() {
-        println!("\n{}", Table { rows: &*table_rows },);
+        let mut out = format!("{}\n", header(song, &tags));
+        let status = follow::advance(&self.status, since_update);
+        if let Some(bar) = Progress::from_status(&status).and_then(|p| p.bar()) {
+            out.push_str(&format!("{}\n", bar));
+        }
+        if !table_rows.is_empty() {
+            out.push_str(&format!("\n{}\n", Table::new(&table_rows)));
+        }
+        out
     }
-    Ok(())
+
+    fn title(&self) -> Option<String> {
+        let song = self.song.as_ref()?;
+        Some(match (&song.artist, &song.title) {
+            (Some(artist), Some(title)) => format!("{} - {}", artist, title),
+            (None, Some(title)) => title.clone(),
+            _ => song.file.clone(),
+        })
+    }
+}
+
+fn row<'a>(label: &'a str, value: &'a str) -> Row<'a> {
+    Row::new(vec![
+        FormattedString::new(label).style(theme().tag_label),
+        FormattedString::new(value).style(theme().tag_value),
+    ])
 }
 
 fn header(song: &Song, tags: &Tags) -> String {
@@ -58,16 +234,16 @@ fn classical_work_description(tags: &Tags) -> Option<String> {
 
     Some(format!(
         "{}\n{}\n{}",
-        FormattedString::new(&*tags.get_option_joined("COMPOSER")?).style(Style::Bold),
-        FormattedString::new(&*tags.get_option_joined("WORK")?).style(Style::Bold),
-        FormattedString::new(&*title).style(Style::Bold)
+        FormattedString::new(&tags.get_option_joined("COMPOSER")?).style(theme().header),
+        FormattedString::new(&tags.get_option_joined("WORK")?).style(theme().header),
+        FormattedString::new(&title).style(theme().header)
     ))
 }
 
 fn popular_music_title(song: &Song) -> Option<String> {
     Some(format!(
         "{}\n{}",
-        FormattedString::new(song.artist.as_deref()?).style(Style::Bold),
-        FormattedString::new(&*song.title.as_deref()?).style(Style::Bold),
+        FormattedString::new(song.artist.as_deref()?).style(theme().header),
+        FormattedString::new(song.title.as_deref()?).style(theme().header),
     ))
 }
@@ -0,0 +1,52 @@
+use crate::autodj;
+use crate::cli::SearchQuery;
+use crate::error::Error;
+use crate::random::Random;
+use crate::raw;
+use mpdrs::Song;
+
+// All songs of a random album, chosen uniformly among the albums with songs
+// matching query, by disc and track number.
+pub fn album(raw: &mut raw::Connection, query: &SearchQuery) -> Result<Vec<Song>, Error> {
+    let mut albums = autodj::albums(&autodj::matching(raw, query)?);
+    if albums.is_empty() {
+        return Ok(vec![]);
+    }
+    let i = Random::from_clock().below(albums.len());
+    autodj::whole_album(raw, albums.swap_remove(i))
+}
+
+// n random songs matching query, in random order.
+pub fn tracks(
+    raw: &mut raw::Connection,
+    query: &SearchQuery,
+    n: usize,
+) -> Result<Vec<Song>, Error> {
+    let mut songs = autodj::matching(raw, query)?;
+    Random::from_clock().shuffle(&mut songs);
+    songs.truncate(n);
+    Ok(songs)
+}
+
+// Add the songs to the end of the queue, and start playing the first of them
+// if play is set.
+pub fn enqueue(
+    client: &mut mpdrs::Client,
+    raw: &mut raw::Connection,
+    songs: &[Song],
+    play: bool,
+) -> Result<(), Error> {
+    if songs.is_empty() {
+        println!("No songs match the query.");
+        return Ok(());
+    }
+    let position = client.status()?.queue_len.to_string();
+    let mut commands = songs
+        .iter()
+        .map(|song| ("add", vec![&*song.file]))
+        .collect::<Vec<_>>();
+    if play {
+        commands.push(("play", vec![&*position]));
+    }
+    raw.command_list(&commands)
+}
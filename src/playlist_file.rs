@@ -0,0 +1,432 @@
+use crate::ansi::{theme, FormattedString};
+use crate::error::{Error, WithContext};
+use crate::json;
+use crate::tags::Tags;
+use mpdrs::Song;
+use std::fs::File;
+use std::io::{Read, Write};
+use std::path::{Component, Path, PathBuf};
+use std::str::FromStr;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Format {
+    M3u,
+    Pls,
+    Xspf,
+    Json,
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match &*s.to_lowercase() {
+            "m3u" | "m3u8" => Ok(Format::M3u),
+            "pls" => Ok(Format::Pls),
+            "xspf" => Ok(Format::Xspf),
+            "json" => Ok(Format::Json),
+            _ => Err(format!("unknown playlist format: {}", s)),
+        }
+    }
+}
+
+impl Format {
+    fn from_path(path: &Path) -> Option<Format> {
+        path.extension()
+            .and_then(|e| e.to_str())
+            .and_then(|e| e.parse().ok())
+    }
+
+    fn sniff(contents: &str) -> Format {
+        let start = contents.trim_start();
+        if start.starts_with("<?xml") || start.starts_with("<playlist") {
+            Format::Xspf
+        } else if start.starts_with("[playlist]") {
+            Format::Pls
+        } else {
+            Format::M3u
+        }
+    }
+}
+
+pub fn export(
+    queue: &[Song],
+    format: Option<Format>,
+    output: Option<&str>,
+    music_directory: Option<&str>,
+) -> Result<(), Error> {
+    let format = format
+        .or_else(|| output.and_then(|o| Format::from_path(Path::new(o))))
+        .unwrap_or(Format::M3u);
+
+    let contents = match format {
+        Format::M3u => to_m3u(queue, music_directory),
+        Format::Xspf => to_xspf(queue, music_directory),
+        Format::Json => {
+            let songs = queue.iter().map(json::song).collect();
+            format!("{}\n", json::Value::Array(songs))
+        }
+        Format::Pls => return Err(Error::Playlist("Export to PLS is not supported.".into())),
+    };
+
+    match output {
+        None | Some("-") => std::io::stdout()
+            .lock()
+            .write_all(contents.as_bytes())
+            .context("writing playlist to stdout"),
+        Some(path) => File::create(path)
+            .context("creating playlist file")?
+            .write_all(contents.as_bytes())
+            .context("writing playlist file"),
+    }
+}
+
+pub fn import(
+    client: &mut mpdrs::Client,
+    path: &str,
+    music_directory: Option<&str>,
+) -> Result<(), Error> {
+    let mut contents = String::new();
+    File::open(path)
+        .context("opening playlist file")?
+        .read_to_string(&mut contents)
+        .context("reading playlist file")?;
+
+    let path = Path::new(path);
+    let format = Format::from_path(path).unwrap_or_else(|| Format::sniff(&contents));
+    let locations = match format {
+        Format::M3u => parse_m3u(&contents),
+        Format::Pls => parse_pls(&contents),
+        Format::Xspf => parse_xspf(&contents),
+        Format::Json => return Err(Error::Playlist("Import from JSON is not supported.".into())),
+    };
+
+    let base_dir = path.parent().unwrap_or_else(|| Path::new(""));
+    let mut unmatched = vec![];
+    let mut added = 0;
+    for location in locations {
+        let uri = match to_mpd_uri(&location, base_dir, music_directory, format) {
+            Some(uri) => uri,
+            None => {
+                unmatched.push(location);
+                continue;
+            }
+        };
+        log::trace!("Mapped playlist entry {} to {}", location, uri);
+        match client.add(&uri) {
+            Ok(()) => added += 1,
+            Err(mpdrs::error::Error::Server(e)) => {
+                log::trace!("MPD refused to add {}: {}", uri, e);
+                unmatched.push(location);
+            }
+            Err(e) => return Err(e.into()),
+        }
+    }
+
+    println!("Added {} songs to the queue.", added);
+    if !unmatched.is_empty() {
+        println!(
+            "{}",
+            FormattedString::new("Could not match the following entries:").style(theme().header)
+        );
+        for location in unmatched {
+            println!("{}", location);
+        }
+    }
+    Ok(())
+}
+
+fn local_path(song: &Song, music_directory: Option<&str>) -> String {
+    match music_directory {
+        Some(dir) if !is_url(&song.file) => {
+            format!("{}/{}", dir.trim_end_matches('/'), song.file)
+        }
+        _ => song.file.clone(),
+    }
+}
+
+fn display_title(song: &Song) -> String {
+    let tags = Tags::from_song(song);
+    match (tags.get_option_joined("artist"), song.title.as_ref()) {
+        (Some(artist), Some(title)) => format!("{} - {}", artist, title),
+        (None, Some(title)) => title.clone(),
+        _ => song.file.clone(),
+    }
+}
+
+fn to_m3u(queue: &[Song], music_directory: Option<&str>) -> String {
+    let mut out = String::from("#EXTM3U\n");
+    for song in queue {
+        let secs = song.duration.map_or(-1, |d| d.as_secs() as i64);
+        out.push_str(&format!("#EXTINF:{},{}\n", secs, display_title(song)));
+        out.push_str(&local_path(song, music_directory));
+        out.push('\n');
+    }
+    out
+}
+
+fn to_xspf(queue: &[Song], music_directory: Option<&str>) -> String {
+    let mut out = String::from(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+         <playlist version=\"1\" xmlns=\"http://xspf.org/ns/0/\">\n  <trackList>\n",
+    );
+    for song in queue {
+        let tags = Tags::from_song(song);
+        let location = if is_url(&song.file) {
+            song.file.clone()
+        } else if music_directory.is_some() {
+            format!(
+                "file://{}",
+                percent_encode(&local_path(song, music_directory))
+            )
+        } else {
+            percent_encode(&song.file)
+        };
+
+        out.push_str("    <track>\n");
+        out.push_str(&format!(
+            "      <location>{}</location>\n",
+            xml_escape(&location)
+        ));
+        let elements = [
+            ("title", song.title.clone()),
+            ("creator", tags.get_option_joined("artist")),
+            ("album", tags.get_option_joined("album")),
+            (
+                "trackNum",
+                tags.get("track")
+                    .first()
+                    .and_then(|t| t.split('/').next())
+                    .and_then(|t| t.trim().parse::<u32>().ok())
+                    .map(|t| t.to_string()),
+            ),
+            ("duration", song.duration.map(|d| d.as_millis().to_string())),
+        ];
+        for (element, value) in elements.iter() {
+            if let Some(value) = value {
+                out.push_str(&format!(
+                    "      <{0}>{1}</{0}>\n",
+                    element,
+                    xml_escape(value)
+                ));
+            }
+        }
+        out.push_str("    </track>\n");
+    }
+    out.push_str("  </trackList>\n</playlist>\n");
+    out
+}
+
+fn parse_m3u(contents: &str) -> Vec<String> {
+    contents
+        .lines()
+        .map(|l| l.trim_start_matches('\u{feff}').trim())
+        .filter(|l| !l.is_empty() && !l.starts_with('#'))
+        .map(String::from)
+        .collect()
+}
+
+fn parse_pls(contents: &str) -> Vec<String> {
+    let mut entries = contents
+        .lines()
+        .filter_map(|l| {
+            let (key, value) = l.trim().split_once('=')?;
+            let index = key.trim().strip_prefix("File")?.parse::<u32>().ok()?;
+            Some((index, value.trim().to_string()))
+        })
+        .collect::<Vec<_>>();
+    entries.sort_by_key(|(i, _)| *i);
+    entries.into_iter().map(|(_, v)| v).collect()
+}
+
+fn parse_xspf(contents: &str) -> Vec<String> {
+    let mut locations = vec![];
+    let mut rest = contents;
+    while let Some(start) = rest.find("<location>") {
+        rest = &rest[start + "<location>".len()..];
+        if let Some(end) = rest.find("</location>") {
+            locations.push(xml_unescape(rest[..end].trim()));
+            rest = &rest[end..];
+        }
+    }
+    locations
+}
+
+// Map an entry from a playlist file to an URI in the MPD database. Relative
+// paths are resolved against the directory of the playlist file if a music
+// directory is configured, and otherwise assumed to already be MPD URIs.
+fn to_mpd_uri(
+    location: &str,
+    base_dir: &Path,
+    music_directory: Option<&str>,
+    format: Format,
+) -> Option<String> {
+    let path = if let Some(path) = location.strip_prefix("file://") {
+        PathBuf::from(percent_decode(path.trim_start_matches("localhost")))
+    } else if is_url(location) {
+        return Some(location.to_string());
+    } else if format == Format::Xspf {
+        PathBuf::from(percent_decode(location))
+    } else {
+        PathBuf::from(location)
+    };
+
+    let music_directory = match music_directory {
+        Some(dir) => Path::new(dir),
+        None if path.is_relative() => return path.to_str().map(String::from),
+        None => return None,
+    };
+
+    let absolute = normalize(&base_dir.join(&path));
+    match absolute.strip_prefix(normalize(music_directory)) {
+        Ok(uri) => uri.to_str().map(String::from),
+        Err(_) if path.is_relative() => path.to_str().map(String::from),
+        Err(_) => None,
+    }
+}
+
+fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => (),
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            c => normalized.push(c),
+        }
+    }
+    normalized
+}
+
+fn is_url(s: &str) -> bool {
+    match s.find("://") {
+        Some(i) => s[..i]
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '+'),
+        None => false,
+    }
+}
+
+fn percent_encode(s: &str) -> String {
+    s.bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' | b'/' => {
+                (b as char).to_string()
+            }
+            b => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+fn percent_decode(s: &str) -> String {
+    let bytes = s.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = bytes
+            .get(i + 1..i + 3)
+            .and_then(|h| std::str::from_utf8(h).ok())
+            .and_then(|h| u8::from_str_radix(h, 16).ok());
+        match (bytes[i], hex) {
+            (b'%', Some(b)) => {
+                out.push(b);
+                i += 3;
+            }
+            (b, _) => {
+                out.push(b);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+fn xml_escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+fn xml_unescape(s: &str) -> String {
+    s.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn m3u() {
+        let contents = "#EXTM3U\n#EXTINF:10,A - B\n/music/a.flac\n\nb/c.mp3\n";
+        assert_eq!(parse_m3u(contents), vec!["/music/a.flac", "b/c.mp3"]);
+    }
+
+    #[test]
+    fn pls() {
+        let contents = "[playlist]\nFile2=b.mp3\nTitle1=A\nFile1=a.mp3\nNumberOfEntries=2\n";
+        assert_eq!(parse_pls(contents), vec!["a.mp3", "b.mp3"]);
+    }
+
+    #[test]
+    fn xspf_round_trip() {
+        let song = Song {
+            file: "Miles Davis/Kind of Blue/01 So What & More.flac".into(),
+            title: Some("So What".into()),
+            ..Song::default()
+        };
+        let xspf = to_xspf(&[song], Some("/srv/music/"));
+        let locations = parse_xspf(&xspf);
+        assert_eq!(
+            locations,
+            vec![
+                "file:///srv/music/Miles%20Davis/Kind%20of%20Blue/01%20So%20What%20%26%20More.flac"
+            ]
+        );
+        assert_eq!(
+            to_mpd_uri(
+                &locations[0],
+                Path::new(""),
+                Some("/srv/music"),
+                Format::Xspf
+            ),
+            Some("Miles Davis/Kind of Blue/01 So What & More.flac".into())
+        );
+    }
+
+    #[test]
+    fn map_local_paths() {
+        let music = Some("/srv/music");
+        let base = Path::new("/home/user/playlists");
+        assert_eq!(
+            to_mpd_uri("/srv/music/a/b.flac", base, music, Format::M3u),
+            Some("a/b.flac".into())
+        );
+        assert_eq!(
+            to_mpd_uri("../../../srv/music/a/b.flac", base, music, Format::M3u),
+            Some("a/b.flac".into())
+        );
+        assert_eq!(
+            to_mpd_uri("/elsewhere/b.flac", base, music, Format::M3u),
+            None
+        );
+        assert_eq!(
+            to_mpd_uri("http://radio/stream", base, music, Format::M3u),
+            Some("http://radio/stream".into())
+        );
+        assert_eq!(
+            to_mpd_uri("a/b.flac", base, None, Format::M3u),
+            Some("a/b.flac".into())
+        );
+        assert_eq!(
+            to_mpd_uri("/srv/music/a/b.flac", base, None, Format::M3u),
+            None
+        );
+    }
+}
@@ -0,0 +1,124 @@
+use crate::ansi::{is_dumb_terminal, terminal_width, theme, FormattedString};
+use crate::status::duration_format;
+use crate::unicode;
+use std::time::Duration;
+
+// Bars narrower than this are not worth printing.
+const MIN_BAR_WIDTH: usize = 10;
+const DEFAULT_WIDTH: usize = 80;
+
+// Playback progress of the current song. Streams have no duration.
+pub struct Progress {
+    pub elapsed: Duration,
+    pub duration: Option<Duration>,
+}
+
+impl Progress {
+    pub fn from_status(status: &mpdrs::Status) -> Option<Progress> {
+        if status.state == mpdrs::State::Stop {
+            return None;
+        }
+        let elapsed = status
+            .elapsed
+            .or_else(|| status.time.map(|(elapsed, _)| elapsed))?;
+        let duration = status
+            .duration
+            .or_else(|| status.time.map(|(_, total)| total))
+            .filter(|d| !d.is_zero());
+        Some(Progress { elapsed, duration })
+    }
+
+    pub fn remaining(&self) -> Option<Duration> {
+        self.duration.map(|d| d.saturating_sub(self.elapsed))
+    }
+
+    pub fn fraction(&self) -> Option<f64> {
+        self.duration
+            .map(|d| (self.elapsed.as_secs_f64() / d.as_secs_f64()).clamp(0., 1.))
+    }
+
+    // Elapsed and total time, such as "01:05/09:46 (11%)".
+    pub fn time(&self) -> String {
+        match (self.duration, self.fraction()) {
+            (Some(duration), Some(fraction)) => format!(
+                "{}/{} ({:2.0}%)",
+                duration_format(&self.elapsed),
+                duration_format(&duration),
+                (100. * fraction).trunc()
+            ),
+            _ => duration_format(&self.elapsed),
+        }
+    }
+
+    // The elapsed time, a progress bar and the remaining time, filling the
+    // width of the terminal. None if stdout is not a terminal, as the bar
+    // would only clutter the output for scripts.
+    pub fn bar(&self) -> Option<String> {
+        if unsafe { libc::isatty(libc::STDOUT_FILENO) } != 1 {
+            return None;
+        }
+        let elapsed = duration_format(&self.elapsed);
+        let (remaining, fraction) = match (self.remaining(), self.fraction()) {
+            (Some(remaining), Some(fraction)) => {
+                (format!("-{}", duration_format(&remaining)), fraction)
+            }
+            _ => return Some(elapsed),
+        };
+        let width = terminal_width().unwrap_or(DEFAULT_WIDTH);
+        let bar_width =
+            width.saturating_sub(unicode::width(&elapsed) + unicode::width(&remaining) + 2);
+        if bar_width < MIN_BAR_WIDTH {
+            return Some(format!("{} {}", elapsed, remaining));
+        }
+        let (filled, empty) = bar_parts(fraction, bar_width);
+        Some(format!(
+            "{} {}{} {}",
+            elapsed,
+            FormattedString::new(&filled).style(theme().progress),
+            FormattedString::new(&empty).style(theme().progress_remaining),
+            remaining
+        ))
+    }
+}
+
+fn bar_parts(fraction: f64, width: usize) -> (String, String) {
+    let filled = ((fraction * width as f64).round() as usize).min(width);
+    let (fill, empty) = if is_dumb_terminal() {
+        ("#", "-")
+    } else {
+        ("━", "─")
+    };
+    (fill.repeat(filled), empty.repeat(width - filled))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn time() {
+        let progress = Progress {
+            elapsed: Duration::from_secs(65),
+            duration: Some(Duration::from_secs(4800)),
+        };
+        assert_eq!(progress.time(), "01:05/1:20:00 ( 1%)");
+        assert_eq!(progress.remaining(), Some(Duration::from_secs(4735)));
+        let stream = Progress {
+            elapsed: Duration::from_secs(3725),
+            duration: None,
+        };
+        assert_eq!(stream.time(), "1:02:05");
+        assert_eq!(stream.fraction(), None);
+    }
+
+    #[test]
+    fn bar_widths() {
+        let parts = |fraction, width| {
+            let (filled, empty) = bar_parts(fraction, width);
+            (filled.chars().count(), empty.chars().count())
+        };
+        assert_eq!(parts(0., 10), (0, 10));
+        assert_eq!(parts(0.26, 10), (3, 7));
+        assert_eq!(parts(1., 10), (10, 0));
+    }
+}
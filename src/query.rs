@@ -0,0 +1,590 @@
+use crate::unicode;
+use std::fmt;
+
+// A compact query language, which is compiled to an MPD filter expression.
+// A query is a list of terms, which all have to match:
+//
+//     artist:"Miles Davis" -genre:live date>=1960 (album~blue OR title~blue)
+//
+// A term is tag:value for equality, tag~value for substrings, tag=~value for
+// regular expressions, or tag>=number (also >, <= and <) for values starting
+// with a number in the range. A term without a tag matches any tag. Terms
+// are negated by a leading -, and grouped with parentheses and OR.
+
+// The tags known to MPD, for telling tag and value pairs from queries.
+static TAGS: &[&str] = &[
+    "any",
+    "file",
+    "base",
+    "artist",
+    "artistsort",
+    "album",
+    "albumsort",
+    "albumartist",
+    "albumartistsort",
+    "title",
+    "titlesort",
+    "track",
+    "name",
+    "genre",
+    "mood",
+    "date",
+    "originaldate",
+    "composer",
+    "composersort",
+    "performer",
+    "conductor",
+    "work",
+    "ensemble",
+    "movement",
+    "movementnumber",
+    "showmovement",
+    "location",
+    "grouping",
+    "comment",
+    "disc",
+    "label",
+    "musicbrainz_artistid",
+    "musicbrainz_albumid",
+    "musicbrainz_albumartistid",
+    "musicbrainz_trackid",
+    "musicbrainz_releasetrackid",
+    "musicbrainz_releasegroupid",
+    "musicbrainz_workid",
+];
+
+// Operators which only appear in MPD filter expressions.
+static EXPRESSION_OPERATORS: &[&str] = &[
+    "==",
+    "!=",
+    "!~",
+    " =~ ",
+    " contains ",
+    " starts_with ",
+    "(!",
+    "(base ",
+    "(modified-since ",
+    "(added-since ",
+];
+
+pub fn is_tag(s: &str) -> bool {
+    TAGS.contains(&&*s.to_lowercase())
+}
+
+// Whether s is written in the MPD filter syntax, rather than as a query.
+pub fn is_expression(s: &str) -> bool {
+    s.trim_start().starts_with('(') && EXPRESSION_OPERATORS.iter().any(|op| s.contains(op))
+}
+
+#[derive(Debug)]
+pub struct Error {
+    query: String,
+    // Byte offset of the error in query.
+    offset: usize,
+    message: String,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let column = unicode::width(&self.query[..self.offset]);
+        write!(
+            f,
+            "invalid query, {} at column {}:\n    {}\n    {}^",
+            self.message,
+            column + 1,
+            self.query,
+            " ".repeat(column)
+        )
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Op {
+    Equal,
+    Contains,
+    Regex,
+    AtLeast,
+    AtMost,
+    Greater,
+    Less,
+}
+
+// Longer operators first, so that >= is not read as >.
+static OPERATORS: &[(&str, Op)] = &[
+    ("=~", Op::Regex),
+    (">=", Op::AtLeast),
+    ("<=", Op::AtMost),
+    (":", Op::Equal),
+    ("~", Op::Contains),
+    (">", Op::Greater),
+    ("<", Op::Less),
+];
+
+#[derive(Debug, PartialEq, Eq)]
+enum Token {
+    Open,
+    Close,
+    Not,
+    Or,
+    Term {
+        tag: String,
+        op: Op,
+        value: String,
+        value_offset: usize,
+    },
+    Word(String),
+}
+
+struct Lexed {
+    token: Token,
+    offset: usize,
+}
+
+// Compile the arguments of a query to an MPD filter expression. The
+// arguments are treated as if joined by spaces.
+pub fn compile(args: &[String]) -> Result<String, Error> {
+    let query = args.join(" ");
+    let error = |offset, message: &str| Error {
+        query: query.clone(),
+        offset,
+        message: message.to_string(),
+    };
+    let mut tokens = vec![];
+    let mut base = 0;
+    for arg in args {
+        lex(arg, base, &mut tokens).map_err(|(offset, message)| error(offset, message))?;
+        base += arg.len() + 1;
+    }
+    let mut parser = Parser {
+        tokens: &tokens,
+        pos: 0,
+        end: query.len(),
+    };
+    let expression = parser
+        .or()
+        .and_then(|expression| match parser.tokens.get(parser.pos) {
+            Some(lexed) => Err((lexed.offset, "unexpected ')'")),
+            None => Ok(expression),
+        })
+        .map_err(|(offset, message)| error(offset, message))?;
+    Ok(expression)
+}
+
+// Split a query written as a single string, such as in the configuration
+// file, into arguments as a shell would, at whitespace outside of quotes. The
+// quotes are kept for the lexer, so that an unquoted value never runs on
+// into the next word.
+pub fn split(s: &str) -> Vec<String> {
+    let mut args = vec![];
+    let mut arg = String::new();
+    let mut quote = None;
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if quote.is_none() && c.is_whitespace() {
+            if !arg.is_empty() {
+                args.push(std::mem::take(&mut arg));
+            }
+            continue;
+        }
+        arg.push(c);
+        match quote {
+            None if c == '"' || c == '\'' => quote = Some(c),
+            Some(_) if c == '\\' => arg.extend(chars.next()),
+            Some(q) if c == q => quote = None,
+            _ => (),
+        }
+    }
+    if !arg.is_empty() {
+        args.push(arg);
+    }
+    args
+}
+
+type LexError = (usize, &'static str);
+
+fn lex(arg: &str, base: usize, tokens: &mut Vec<Lexed>) -> Result<(), LexError> {
+    let is_tag_char = |c: char| c.is_alphanumeric() || c == '_';
+    let is_word_end = |c: char| c.is_whitespace() || c == '(' || c == ')';
+    let mut i = 0;
+    while let Some(c) = arg[i..].chars().next() {
+        let rest = &arg[i..];
+        let offset = base + i;
+        let mut push = |token| tokens.push(Lexed { token, offset });
+        if c.is_whitespace() {
+            i += c.len_utf8();
+        } else if c == '(' {
+            push(Token::Open);
+            i += 1;
+        } else if c == ')' {
+            push(Token::Close);
+            i += 1;
+        } else if c == '-' && rest[1..].starts_with(|c: char| !is_word_end(c)) {
+            push(Token::Not);
+            i += 1;
+        } else if c == '"' || c == '\'' {
+            let (value, len) = quoted(rest, offset)?;
+            push(Token::Word(value));
+            i += len;
+        } else {
+            let tag_len = rest.find(|c| !is_tag_char(c)).unwrap_or(rest.len());
+            let op = OPERATORS
+                .iter()
+                .find(|(text, _)| rest[tag_len..].starts_with(text));
+            match op {
+                Some((text, op)) if tag_len > 0 => {
+                    let value_start = i + tag_len + text.len();
+                    let (value, len) = value(&arg[value_start..], base + value_start)?;
+                    push(Token::Term {
+                        tag: rest[..tag_len].to_string(),
+                        op: *op,
+                        value,
+                        value_offset: base + value_start,
+                    });
+                    i = value_start + len;
+                }
+                _ => {
+                    let len = rest.find(is_word_end).unwrap_or(rest.len());
+                    push(match &rest[..len] {
+                        "OR" => Token::Or,
+                        word => Token::Word(word.to_string()),
+                    });
+                    i += len;
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+// A quoted string at the start of s, in which a backslash escapes the next
+// character. Returns the string and its length in s, including the quotes.
+fn quoted(s: &str, offset: usize) -> Result<(String, usize), LexError> {
+    let mut chars = s.char_indices();
+    let quote = chars.next().map(|(_, c)| c);
+    let mut value = String::new();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '\\' => value.extend(chars.next().map(|(_, c)| c)),
+            c if Some(c) == quote => return Ok((value, i + 1)),
+            c => value.push(c),
+        }
+    }
+    Err((offset, "unterminated quote"))
+}
+
+// The value of a term, at the start of s. An unquoted value ends at a space
+// or parenthesis, unless the rest of the argument is plain words: the shell
+// turns artist:"Miles Davis" into a single argument without the quotes.
+fn value(s: &str, offset: usize) -> Result<(String, usize), LexError> {
+    match s.chars().next() {
+        Some('"' | '\'') => quoted(s, offset),
+        Some(c) if !c.is_whitespace() && c != '(' && c != ')' => {
+            let len = s
+                .find(|c: char| c.is_whitespace() || c == '(' || c == ')')
+                .unwrap_or(s.len());
+            let rest = &s[len..];
+            let plain = !rest.contains(['(', ')', '"', '\'', ':', '~', '<', '>'])
+                && !rest
+                    .split_whitespace()
+                    .any(|word| word == "OR" || word.starts_with('-'));
+            if plain {
+                Ok((s.trim_end().to_string(), s.len()))
+            } else {
+                Ok((s[..len].to_string(), len))
+            }
+        }
+        _ => Err((offset, "expected a value")),
+    }
+}
+
+struct Parser<'a> {
+    tokens: &'a [Lexed],
+    pos: usize,
+    end: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn peek(&self) -> Option<&'a Token> {
+        self.tokens.get(self.pos).map(|lexed| &lexed.token)
+    }
+
+    fn offset(&self) -> usize {
+        self.tokens
+            .get(self.pos)
+            .map_or(self.end, |lexed| lexed.offset)
+    }
+
+    // Alternatives separated by OR. MPD has no OR, but (!((!A) AND (!B))) is
+    // the same as A OR B.
+    fn or(&mut self) -> Result<String, LexError> {
+        let mut alternatives = vec![self.and()?];
+        while self.peek() == Some(&Token::Or) {
+            self.pos += 1;
+            alternatives.push(self.and()?);
+        }
+        if alternatives.len() == 1 {
+            return Ok(alternatives.remove(0));
+        }
+        let negated = alternatives
+            .iter()
+            .map(|a| format!("(!{})", a))
+            .collect::<Vec<_>>();
+        Ok(format!("(!({}))", negated.join(" AND ")))
+    }
+
+    fn and(&mut self) -> Result<String, LexError> {
+        let mut terms = vec![];
+        while !matches!(self.peek(), None | Some(Token::Or | Token::Close)) {
+            terms.push(self.unary()?);
+        }
+        match terms.len() {
+            0 => Err((self.offset(), "expected a term")),
+            1 => Ok(terms.remove(0)),
+            _ => Ok(format!("({})", terms.join(" AND "))),
+        }
+    }
+
+    fn unary(&mut self) -> Result<String, LexError> {
+        let offset = self.offset();
+        let token = self.peek().ok_or((offset, "expected a term"))?;
+        self.pos += 1;
+        match token {
+            Token::Not => Ok(format!("(!{})", self.unary()?)),
+            Token::Open => {
+                let expression = self.or()?;
+                if self.peek() != Some(&Token::Close) {
+                    return Err((self.offset(), "expected ')'"));
+                }
+                self.pos += 1;
+                Ok(expression)
+            }
+            Token::Term {
+                tag,
+                op,
+                value,
+                value_offset,
+            } => term(tag, *op, value).map_err(|message| (*value_offset, message)),
+            Token::Word(word) => Ok(format!("(any contains {})", quote(word))),
+            Token::Or | Token::Close => Err((offset, "expected a term")),
+        }
+    }
+}
+
+fn term(tag: &str, op: Op, value: &str) -> Result<String, &'static str> {
+    let number = || value.parse::<u64>().map_err(|_| "expected a number");
+    let regex = match op {
+        Op::Equal if tag.eq_ignore_ascii_case("base") => {
+            return Ok(format!("(base {})", quote(value)))
+        }
+        Op::Equal => return Ok(format!("({} == {})", tag, quote(value))),
+        Op::Contains => return Ok(format!("({} contains {})", tag, quote(value))),
+        Op::Regex => value.to_string(),
+        Op::AtLeast => number_regex(at_least(number()?)),
+        Op::Greater => number_regex(at_least(
+            number()?.checked_add(1).ok_or("number too large")?,
+        )),
+        Op::AtMost => number_regex(at_most(number()?)),
+        Op::Less => number_regex(at_most(
+            number()?.checked_sub(1).ok_or("no number is less than 0")?,
+        )),
+    };
+    Ok(format!("({} =~ {})", tag, quote(&regex)))
+}
+
+// A string in an MPD filter expression.
+fn quote(s: &str) -> String {
+    format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+// MPD can only compare tags as text, so numeric comparisons are regular
+// expressions for the numbers in the range, matching values that start with
+// such a number, ignoring leading zeros, such as dates.
+fn number_regex(alternatives: Vec<String>) -> String {
+    format!("^0*({})([^0-9]|$)", alternatives.join("|"))
+}
+
+fn digit_range(low: u32, high: u32) -> String {
+    if low == high {
+        low.to_string()
+    } else {
+        format!("[{}-{}]", low, high)
+    }
+}
+
+fn any_digits(n: usize) -> String {
+    match n {
+        0 => String::new(),
+        1 => "[0-9]".to_string(),
+        n => format!("[0-9]{{{}}}", n),
+    }
+}
+
+fn digits(n: u64) -> (String, Vec<u32>) {
+    let s = n.to_string();
+    let digits = s.chars().filter_map(|c| c.to_digit(10)).collect();
+    (s, digits)
+}
+
+// Numbers without leading zeros which are at least n: n itself, numbers of
+// the same length which are larger at some digit, and longer numbers.
+fn at_least(n: u64) -> Vec<String> {
+    let (s, digits) = digits(n);
+    let mut alternatives = vec![s.clone()];
+    for (i, &digit) in digits.iter().enumerate() {
+        if digit < 9 {
+            alternatives.push(format!(
+                "{}{}{}",
+                &s[..i],
+                digit_range(digit + 1, 9),
+                any_digits(digits.len() - i - 1)
+            ));
+        }
+    }
+    alternatives.push(format!("[1-9][0-9]{{{},}}", digits.len()));
+    alternatives
+}
+
+// Numbers without leading zeros which are at most n: n itself, numbers of
+// the same length which are smaller at some digit, and shorter numbers.
+fn at_most(n: u64) -> Vec<String> {
+    let (s, digits) = digits(n);
+    let mut alternatives = vec![s.clone()];
+    for (i, &digit) in digits.iter().enumerate() {
+        let low = if i == 0 && digits.len() > 1 { 1 } else { 0 };
+        if digit > low {
+            alternatives.push(format!(
+                "{}{}{}",
+                &s[..i],
+                digit_range(low, digit - 1),
+                any_digits(digits.len() - i - 1)
+            ));
+        }
+    }
+    if digits.len() > 1 {
+        alternatives.push(format!("[0-9]{{1,{}}}", digits.len() - 1));
+    }
+    alternatives
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn compile_str(query: &str) -> Result<String, String> {
+        compile(&[query.to_string()]).map_err(|e| e.to_string())
+    }
+
+    #[test]
+    fn queries() {
+        assert_eq!(
+            compile_str(r#"artist:"Miles Davis" -genre:live (album~blue OR title~blue)"#),
+            Ok(concat!(
+                r#"((artist == "Miles Davis") AND (!(genre == "live")) AND "#,
+                r#"(!((!(album contains "blue")) AND (!(title contains "blue")))))"#
+            )
+            .to_string())
+        );
+        assert_eq!(
+            compile_str(r#"So\ What title=~'^so "what\'' "#),
+            Ok(concat!(
+                r#"((any contains "So\\") AND (any contains "What") AND "#,
+                r#"(title =~ "^so \"what'"))"#
+            )
+            .to_string())
+        );
+        assert_eq!(
+            compile_str("date>=1960"),
+            Ok(concat!(
+                r#"(date =~ "^0*(1960|[2-9][0-9]{3}|19[7-9][0-9]|196[1-9]|"#,
+                r#"[1-9][0-9]{4,})([^0-9]|$)")"#
+            )
+            .to_string())
+        );
+    }
+
+    #[test]
+    fn shell_arguments() {
+        let args = [
+            "artist:Miles Davis",
+            "-genre:live",
+            "(album~blue",
+            "OR",
+            "title~blue)",
+        ];
+        let args = args.iter().map(|a| a.to_string()).collect::<Vec<_>>();
+        assert_eq!(
+            compile(&args).unwrap(),
+            concat!(
+                r#"((artist == "Miles Davis") AND (!(genre == "live")) AND "#,
+                r#"(!((!(album contains "blue")) AND (!(title contains "blue")))))"#
+            )
+        );
+        let args = ["genre:Jazz".to_string(), "blue".to_string()];
+        assert_eq!(
+            compile(&args).unwrap(),
+            r#"((genre == "Jazz") AND (any contains "blue"))"#
+        );
+    }
+
+    #[test]
+    fn splitting() {
+        assert_eq!(
+            split(r#" genre:Jazz  blue artist:"Miles Davis" 'it\'s (so)' "#),
+            vec![
+                "genre:Jazz",
+                "blue",
+                r#"artist:"Miles Davis""#,
+                r"'it\'s (so)'"
+            ]
+        );
+        assert_eq!(
+            compile(&split("genre:Jazz blue")).unwrap(),
+            r#"((genre == "Jazz") AND (any contains "blue"))"#
+        );
+    }
+
+    #[test]
+    fn numbers() {
+        assert_eq!(at_least(0), vec!["0", "[1-9]", "[1-9][0-9]{1,}"]);
+        assert_eq!(at_most(5), vec!["5", "[0-4]"]);
+        assert_eq!(
+            at_most(1960),
+            vec!["1960", "1[0-8][0-9]{2}", "19[0-5][0-9]", "[0-9]{1,3}"]
+        );
+        assert_eq!(
+            compile_str("track<1"),
+            Ok(r#"(track =~ "^0*(0)([^0-9]|$)")"#.to_string())
+        );
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(
+            compile_str("artist: -genre:live").unwrap_err(),
+            "invalid query, expected a value at column 8:\n    artist: -genre:live\n           ^"
+        );
+        assert_eq!(
+            compile_str("(a OR b").unwrap_err(),
+            "invalid query, expected ')' at column 8:\n    (a OR b\n           ^"
+        );
+        assert!(compile_str("a)")
+            .unwrap_err()
+            .contains("unexpected ')' at column 2"));
+        assert!(compile_str("date<0").unwrap_err().contains("at column 6"));
+        assert!(compile_str("title:'so")
+            .unwrap_err()
+            .contains("unterminated quote"));
+        assert!(compile_str("a OR")
+            .unwrap_err()
+            .contains("expected a term at column 5"));
+    }
+
+    #[test]
+    fn expressions() {
+        assert!(is_expression(r#"(artist == "Miles Davis")"#));
+        assert!(is_expression(r#"(!(genre contains "live"))"#));
+        assert!(!is_expression("(album~blue OR title~blue)"));
+        assert!(is_tag("AlbumArtist"));
+        assert!(!is_tag("blue"));
+    }
+}
@@ -1,19 +1,19 @@
-use crate::ansi::{FormattedString, Style};
-use crate::table::{Row, Table};
+use crate::album::Group;
+use crate::ansi::{terminal_height, theme, FormattedString, Style};
+use crate::error::Error;
+use crate::follow;
+use crate::format::Template;
+use crate::table::{Align, Row, Table};
 use crate::tags::Tags;
 use mpdrs::Song;
-
-pub fn bold<S: AsRef<str>>(s: S) -> String {
-    FormattedString::new(s.as_ref())
-        .style(Style::Bold)
-        .to_string()
-}
+use std::time::Duration;
 
 fn header(song: &Song) -> Option<String> {
     let tags = Tags::from_song(song);
-    tags.joined(&["work", "composer"], " - ")
-        .or_else(|| tags.joined(&["album", "albumartist"], " - "))
-        .or_else(|| tags.joined(&["album", "artist"], " - "))
+    [Group::Work, Group::Album]
+        .iter()
+        .find_map(|group| group.tags(&tags))
+        .and_then(|keys| tags.joined(&keys, " - "))
 }
 
 struct QueueRow {
@@ -22,15 +22,15 @@ struct QueueRow {
 }
 
 impl QueueRow {
-    fn to_table_row(&self) -> Row {
+    fn to_table_row(&self) -> Row<'_> {
         Row::new(
             self.fields
                 .iter()
                 .map(|s| {
-                    FormattedString::new(&*s).style(if self.is_current {
-                        Style::Bold
+                    FormattedString::new(s).style(if self.is_current {
+                        theme().current
                     } else {
-                        Style::Default
+                        Style::default()
                     })
                 })
                 .collect(),
@@ -38,44 +38,124 @@ impl QueueRow {
     }
 }
 
-fn print_table(rows: &[QueueRow]) {
+fn render_table(rows: &[QueueRow], out: &mut String) {
     let table_rows = rows
         .iter()
         .map(QueueRow::to_table_row)
         .collect::<Vec<Row>>();
-    println!("{}", Table { rows: &*table_rows });
+    out.push_str(&format!(
+        "{}\n",
+        Table::new(&table_rows).align(0, Align::Right)
+    ));
 }
 
 pub fn print(queue: Vec<Song>, current: &Option<Song>) {
+    print!("{}", render(&queue, current).0);
+}
+
+// Render the queue, and return the line of the current song along with it.
+fn render(queue: &[Song], current: &Option<Song>) -> (String, Option<usize>) {
+    let mut out = String::new();
+    let mut lines = 0;
+    let mut current_line = None;
     let mut cur_header = None;
     let mut rows: Vec<QueueRow> = Vec::new();
-    let max_pos = queue.len();
-    let pos_width = (max_pos as f32).log10() as usize + 1;
-    for (pos, song) in queue.into_iter().enumerate() {
+    for (pos, song) in queue.iter().enumerate() {
         let pos = pos + 1;
-        if let Some(h) = header(&song).filter(|h| Some(h) != cur_header.as_ref()) {
+        if let Some(h) = header(song).filter(|h| Some(h) != cur_header.as_ref()) {
             if !rows.is_empty() {
-                print_table(&*rows);
+                render_table(&rows, &mut out);
                 rows.clear();
             }
-            println!("{}", bold(&h));
+            out.push_str(&format!(
+                "{}\n",
+                FormattedString::new(&h).style(theme().header)
+            ));
+            lines += 1;
             cur_header = Some(h);
         }
 
-        let tags = Tags::from_song(&song);
+        let tags = Tags::from_song(song);
         let mut fields = ["movementnumber", "movement"]
             .iter()
             .map(|s| tags.get_option_joined(s))
             .collect::<Option<Vec<String>>>()
             .or_else(|| song.title.clone().map(|t| vec![t]))
             .unwrap_or_else(|| vec![song.file.clone()]);
-        fields.insert(0, format!("{: <width$}", pos, width = pos_width));
-        rows.push(QueueRow {
-            is_current: Some(&song) == current.as_ref(),
-            fields,
-        });
+        fields.insert(0, pos.to_string());
+        let is_current = Some(song) == current.as_ref();
+        if is_current {
+            current_line = Some(lines);
+        }
+        rows.push(QueueRow { is_current, fields });
+        lines += 1;
     }
     if !rows.is_empty() {
-        print_table(&*rows);
+        render_table(&rows, &mut out);
+    }
+    (out, current_line)
+}
+
+pub struct View<'a> {
+    template: Option<&'a Template>,
+    queue: Vec<Song>,
+    current: Option<Song>,
+    // The queue rendered with the template, which may need raw comments.
+    lines: Vec<String>,
+}
+
+impl<'a> View<'a> {
+    pub fn new(template: Option<&'a Template>) -> View<'a> {
+        View {
+            template,
+            queue: vec![],
+            current: None,
+            lines: vec![],
+        }
+    }
+}
+
+impl<'a> follow::View for View<'a> {
+    fn subsystems(&self) -> &'static [&'static str] {
+        &["playlist", "player"]
+    }
+
+    fn update(&mut self, client: &mut mpdrs::Client) -> Result<(), Error> {
+        self.queue = client.queue()?;
+        self.current = client.currentsong()?;
+        if let Some(template) = self.template {
+            self.lines = self
+                .queue
+                .iter()
+                .map(|song| Ok(template.render(song, &template.tags_for(client, song)?)))
+                .collect::<Result<_, Error>>()?;
+        }
+        Ok(())
+    }
+
+    // Scroll the queue so that the current song is visible.
+    fn render(&self, _: Duration) -> String {
+        let (out, current_line) = match self.template {
+            Some(_) => {
+                let current = self.current.as_ref().and_then(|s| s.place).map(|p| p.pos);
+                (
+                    self.lines.iter().map(|l| format!("{}\n", l)).collect(),
+                    current.map(|p| p as usize),
+                )
+            }
+            None => render(&self.queue, &self.current),
+        };
+        let height = terminal_height().unwrap_or(usize::MAX);
+        match current_line {
+            Some(line) if line >= height => {
+                let lines = out.lines().skip(line - height / 2).collect::<Vec<_>>();
+                lines.join("\n")
+            }
+            _ => out,
+        }
+    }
+
+    fn title(&self) -> Option<String> {
+        Some(format!("Queue ({} songs)", self.queue.len()))
     }
 }
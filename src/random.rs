@@ -0,0 +1,79 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// A small pseudo-random number generator (xorshift64*), seeded from the clock
+// and the process ID. Good enough for picking songs, but not for anything
+// which has to be unpredictable.
+pub struct Random(u64);
+
+impl Random {
+    pub fn from_clock() -> Random {
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_nanos() as u64);
+        Random::from_seed(nanos ^ (u64::from(std::process::id()) << 32))
+    }
+
+    pub fn from_seed(seed: u64) -> Random {
+        // Scramble the seed as splitmix64 does, so that similar seeds give
+        // different sequences. The state must never be zero.
+        let mut z = seed.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        Random((z ^ (z >> 31)) | 1)
+    }
+
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    }
+
+    // A number from 0 up to, but not including, n, which must not be 0.
+    pub fn below(&mut self, n: usize) -> usize {
+        ((u128::from(self.next()) * n as u128) >> 64) as usize
+    }
+
+    pub fn shuffle<T>(&mut self, items: &mut [T]) {
+        for i in (1..items.len()).rev() {
+            items.swap(i, self.below(i + 1));
+        }
+    }
+
+    // n of the items, chosen uniformly, in their original order.
+    pub fn sample<T>(&mut self, items: Vec<T>, n: usize) -> Vec<T> {
+        let mut indices = (0..items.len()).collect::<Vec<_>>();
+        self.shuffle(&mut indices);
+        let mut chosen = vec![false; items.len()];
+        for i in indices.into_iter().take(n) {
+            chosen[i] = true;
+        }
+        items
+            .into_iter()
+            .zip(chosen)
+            .filter_map(|(item, chosen)| chosen.then_some(item))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn sampling() {
+        let mut random = Random::from_seed(42);
+        for _ in 0..100 {
+            assert!(random.below(3) < 3);
+        }
+        let sample = random.sample((0..10).collect(), 4);
+        assert_eq!(sample.len(), 4);
+        assert!(sample.windows(2).all(|w| w[0] < w[1]));
+        assert_eq!(random.sample(vec![1, 2], 5), vec![1, 2]);
+
+        let mut items = (0..10).collect::<Vec<_>>();
+        random.shuffle(&mut items);
+        items.sort_unstable();
+        assert_eq!(items, (0..10).collect::<Vec<_>>());
+    }
+}
@@ -0,0 +1,300 @@
+use crate::error::{Error, WithContext};
+use mpdrs::error::{Error as MpdError, ProtoError};
+use mpdrs::lsinfo::LsInfoResponse;
+use mpdrs::reply::Reply;
+use mpdrs::song::QueuePlace;
+use mpdrs::Song;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::TcpStream;
+use std::time::Duration;
+
+// Connection for the parts of the MPD protocol which mpdrs does not support,
+// such as command lists and sorting. It shares the socket with the mpdrs
+// client, which is fine as long as the two are not used at the same time:
+// every command reads its whole response, up to the final OK.
+pub struct Connection {
+    stream: BufReader<TcpStream>,
+    // Set while an idle command is waiting for events.
+    idling: bool,
+    // A partial line read before a timeout.
+    pending: String,
+}
+
+impl Connection {
+    pub fn new(stream: TcpStream) -> Connection {
+        Connection {
+            stream: BufReader::new(stream),
+            idling: false,
+            pending: String::new(),
+        }
+    }
+
+    // Wait for changes in the subsystems, for at most timeout. Returns the
+    // changed subsystems, or None on timeout, in which case the idle command
+    // is still active and the next call continues waiting for it. The mpdrs
+    // client must not be used until this has returned changes.
+    pub fn idle(
+        &mut self,
+        subsystems: &[&str],
+        timeout: Duration,
+    ) -> Result<Option<Vec<String>>, Error> {
+        if !self.idling {
+            let line = command_line("idle", subsystems);
+            self.stream
+                .get_mut()
+                .write_all(line.as_bytes())
+                .context("sending command to MPD")?;
+            self.idling = true;
+        }
+        self.stream
+            .get_ref()
+            .set_read_timeout(Some(timeout))
+            .context("setting socket timeout")?;
+        let result = self.read_idle_response();
+        self.stream
+            .get_ref()
+            .set_read_timeout(None)
+            .context("setting socket timeout")?;
+        result
+    }
+
+    fn read_idle_response(&mut self) -> Result<Option<Vec<String>>, Error> {
+        let mut changed = vec![];
+        loop {
+            match self.stream.read_line(&mut self.pending) {
+                Ok(0) => return Err(MpdError::Proto(ProtoError::NotOk).into()),
+                Ok(_) => (),
+                Err(e)
+                    if matches!(
+                        e.kind(),
+                        std::io::ErrorKind::WouldBlock | std::io::ErrorKind::TimedOut
+                    ) =>
+                {
+                    return Ok(None)
+                }
+                Err(e) => return Err(e).context("reading response from MPD"),
+            }
+            let line = std::mem::take(&mut self.pending);
+            match line.trim_end_matches('\n').parse::<Reply>() {
+                Ok(Reply::Ok) => {
+                    self.idling = false;
+                    return Ok(Some(changed));
+                }
+                Ok(Reply::Ack(e)) => return Err(MpdError::Server(e).into()),
+                Ok(Reply::Pair(_, subsystem)) => changed.push(subsystem),
+                Err(e) => return Err(MpdError::Parse(e).into()),
+            }
+        }
+    }
+
+    pub fn command(
+        &mut self,
+        command: &str,
+        args: &[&str],
+    ) -> Result<Vec<(String, String)>, Error> {
+        let line = command_line(command, args);
+        log::trace!("Sending raw command: {}", line.trim_end());
+        self.stream
+            .get_mut()
+            .write_all(line.as_bytes())
+            .context("sending command to MPD")?;
+        self.read_response()
+    }
+
+    // A command which responds with a chunk of binary data, such as albumart
+    // or readpicture. Returns the data, which is empty if there is none.
+    pub fn binary_command(&mut self, command: &str, args: &[&str]) -> Result<Vec<u8>, Error> {
+        let line = command_line(command, args);
+        log::trace!("Sending raw command: {}", line.trim_end());
+        self.stream
+            .get_mut()
+            .write_all(line.as_bytes())
+            .context("sending command to MPD")?;
+        let mut data = vec![];
+        self.read_response_with_data(&mut data)?;
+        Ok(data)
+    }
+
+    pub fn songs(&mut self, command: &str, args: &[&str]) -> Result<Vec<Song>, Error> {
+        Ok(split(self.command(command, args)?, &["file"])
+            .into_iter()
+            .map(song)
+            .collect())
+    }
+
+    // Directories, playlists and songs, as returned by lsinfo or listallinfo.
+    pub fn entries(&mut self, command: &str, args: &[&str]) -> Result<Vec<LsInfoResponse>, Error> {
+        Ok(split(
+            self.command(command, args)?,
+            &["file", "directory", "playlist"],
+        )
+        .into_iter()
+        .map(entry)
+        .collect())
+    }
+
+    // Run all commands in a single command list, which MPD executes as one
+    // unit, stopping at the first failing command.
+    pub fn command_list(&mut self, commands: &[(&str, Vec<&str>)]) -> Result<(), Error> {
+        let mut buf = String::from("command_list_begin\n");
+        for (command, args) in commands {
+            buf.push_str(&command_line(command, args));
+        }
+        buf.push_str("command_list_end\n");
+        log::trace!("Sending command list with {} commands.", commands.len());
+        self.stream
+            .get_mut()
+            .write_all(buf.as_bytes())
+            .context("sending command list to MPD")?;
+        self.read_response().map(|_| ())
+    }
+
+    fn read_response(&mut self) -> Result<Vec<(String, String)>, Error> {
+        self.read_response_with_data(&mut vec![])
+    }
+
+    // Read a response, which may contain binary data after a binary pair
+    // with its length. The data is appended to data.
+    fn read_response_with_data(
+        &mut self,
+        data: &mut Vec<u8>,
+    ) -> Result<Vec<(String, String)>, Error> {
+        let mut pairs = vec![];
+        loop {
+            let mut line = String::new();
+            if self
+                .stream
+                .read_line(&mut line)
+                .context("reading response from MPD")?
+                == 0
+            {
+                return Err(MpdError::Proto(ProtoError::NotOk).into());
+            }
+            match line.trim_end_matches('\n').parse::<Reply>() {
+                Ok(Reply::Ok) => return Ok(pairs),
+                Ok(Reply::Ack(e)) => return Err(MpdError::Server(e).into()),
+                Ok(Reply::Pair(k, v)) if k == "binary" => {
+                    let len = v.parse::<usize>().map_err(MpdError::from)?;
+                    // The data is followed by a newline.
+                    let mut chunk = vec![0; len + 1];
+                    self.stream
+                        .read_exact(&mut chunk)
+                        .context("reading response from MPD")?;
+                    chunk.pop();
+                    data.extend(chunk);
+                    pairs.push((k, v));
+                }
+                Ok(Reply::Pair(k, v)) => pairs.push((k, v)),
+                Err(e) => return Err(MpdError::Parse(e).into()),
+            }
+        }
+    }
+}
+
+fn command_line(command: &str, args: &[&str]) -> String {
+    let mut line = command.to_string();
+    for arg in args {
+        line.push_str(&format!(
+            " \"{}\"",
+            arg.replace('\\', "\\\\").replace('"', "\\\"")
+        ));
+    }
+    line.push('\n');
+    line
+}
+
+// Split a response into one group of pairs per object, starting at any of
+// the keys.
+pub fn split(pairs: Vec<(String, String)>, keys: &[&str]) -> Vec<Vec<(String, String)>> {
+    let mut groups: Vec<Vec<(String, String)>> = vec![];
+    for pair in pairs {
+        match groups.last_mut() {
+            Some(group) if !keys.contains(&&*pair.0) => group.push(pair),
+            _ if keys.contains(&&*pair.0) => groups.push(vec![pair]),
+            _ => (),
+        }
+    }
+    groups
+}
+
+pub fn entry(pairs: Vec<(String, String)>) -> LsInfoResponse {
+    match &*pairs[0].0 {
+        "directory" => LsInfoResponse::Directory {
+            path: pairs[0].1.clone(),
+            metadata: pairs,
+        },
+        "playlist" => LsInfoResponse::Playlist {
+            path: pairs[0].1.clone(),
+            metadata: pairs,
+        },
+        _ => LsInfoResponse::Song(song(pairs)),
+    }
+}
+
+// Same as the song parsing in mpdrs, which is not public.
+fn song(pairs: Vec<(String, String)>) -> Song {
+    let mut song = Song::default();
+    for (k, v) in pairs {
+        match &*k {
+            "file" => song.file = v,
+            "Title" => song.title = Some(v),
+            "Last-Modified" => song.last_mod = Some(v),
+            "Artist" => song.artist = Some(v),
+            "Name" => song.name = Some(v),
+            "Time" => song.duration = v.parse().ok().map(Duration::from_secs),
+            "Range" => song.range = v.parse().ok(),
+            "Id" | "Pos" | "Prio" => {
+                let place = song.place.get_or_insert_with(QueuePlace::default);
+                match &*k {
+                    "Id" => place.id = v.parse().unwrap_or_default(),
+                    "Pos" => place.pos = v.parse().unwrap_or_default(),
+                    _ => place.prio = v.parse().unwrap_or_default(),
+                }
+            }
+            _ => song.tags.push((k, v)),
+        }
+    }
+    song
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn pairs(p: &[(&str, &str)]) -> Vec<(String, String)> {
+        p.iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn quoting() {
+        assert_eq!(
+            command_line("add", &["a \"b\" \\c"]),
+            "add \"a \\\"b\\\" \\\\c\"\n"
+        );
+    }
+
+    #[test]
+    fn split_songs() {
+        let response = pairs(&[
+            ("directory", "a"),
+            ("file", "a/1.flac"),
+            ("Title", "One"),
+            ("Time", "10"),
+            ("file", "a/2.flac"),
+            ("Pos", "3"),
+            ("Genre", "Jazz"),
+        ]);
+        let songs = split(response, &["file"])
+            .into_iter()
+            .map(song)
+            .collect::<Vec<_>>();
+        assert_eq!(songs.len(), 2);
+        assert_eq!(songs[0].title.as_deref(), Some("One"));
+        assert_eq!(songs[0].duration, Some(Duration::from_secs(10)));
+        assert_eq!(songs[0].place, None);
+        assert_eq!(songs[1].place.map(|p| p.pos), Some(3));
+        assert_eq!(songs[1].tags, pairs(&[("Genre", "Jazz")]));
+    }
+}
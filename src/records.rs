@@ -0,0 +1,139 @@
+use crate::format;
+use crate::tags::Tags;
+use mpdrs::lsinfo::LsInfoResponse;
+use mpdrs::Song;
+use std::io::Write;
+
+// Output for scripts: one record per song or entry, terminated by a newline
+// or a NUL byte. A record is either the bare path, or the requested fields
+// separated by tabs, with tabs, newlines and backslashes escaped.
+#[derive(Default)]
+pub struct Records {
+    pub null: bool,
+    pub fields: Option<Vec<String>>,
+}
+
+impl Records {
+    pub fn is_set(&self) -> bool {
+        self.null || self.fields.is_some()
+    }
+
+    pub fn print_songs(&self, songs: &[Song]) {
+        self.print(songs.iter().map(|song| self.song_record(song)));
+    }
+
+    pub fn print_entries(&self, entries: &[LsInfoResponse]) {
+        self.print(entries.iter().map(|entry| match entry {
+            LsInfoResponse::Song(song) => self.song_record(song),
+            LsInfoResponse::Directory { path, metadata } => {
+                self.entry_record("directory", path, metadata)
+            }
+            LsInfoResponse::Playlist { path, metadata } => {
+                self.entry_record("playlist", path, metadata)
+            }
+        }));
+    }
+
+    pub fn print_values<T: AsRef<str>>(&self, values: &[T]) {
+        self.print(values.iter().map(|v| v.as_ref().to_string()));
+    }
+
+    fn print(&self, records: impl Iterator<Item = String>) {
+        let terminator = if self.null { '\0' } else { '\n' };
+        let mut out = std::io::stdout().lock();
+        for record in records {
+            // Errors writing to stdout are ignored, like println does.
+            let _ = write!(out, "{}{}", record, terminator);
+        }
+    }
+
+    fn song_record(&self, song: &Song) -> String {
+        match &self.fields {
+            Some(fields) => {
+                let tags = Tags::from_song(song);
+                self.join(fields, |field| match &*field.to_lowercase() {
+                    "type" => Some("song".to_string()),
+                    "path" => Some(song.file.clone()),
+                    _ => format::lookup(field, song, &tags),
+                })
+            }
+            None => song.file.clone(),
+        }
+    }
+
+    fn entry_record(&self, kind: &str, path: &str, metadata: &[(String, String)]) -> String {
+        match &self.fields {
+            Some(fields) => self.join(fields, |field| match &*field.to_lowercase() {
+                "type" => Some(kind.to_string()),
+                "path" | "file" => Some(path.to_string()),
+                "mtime" => metadata
+                    .iter()
+                    .find(|(k, _)| k == "Last-Modified")
+                    .map(|(_, v)| v.clone()),
+                _ => None,
+            }),
+            None => path.to_string(),
+        }
+    }
+
+    fn join(&self, fields: &[String], value: impl Fn(&str) -> Option<String>) -> String {
+        fields
+            .iter()
+            .map(|field| escape(&value(field).unwrap_or_default()))
+            .collect::<Vec<_>>()
+            .join("\t")
+    }
+}
+
+pub fn parse_fields(fields: &str) -> Vec<String> {
+    fields
+        .split(',')
+        .map(|f| f.trim().to_string())
+        .filter(|f| !f.is_empty())
+        .collect()
+}
+
+// Escape the characters which separate fields and records. NUL can not occur
+// in MPD responses, so it is left alone.
+fn escape(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            '\t' => escaped.push_str("\\t"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn escaping() {
+        assert_eq!(escape("a\tb\nc\\d\re"), "a\\tb\\nc\\\\d\\re");
+        assert_eq!(escape("plain"), "plain");
+    }
+
+    #[test]
+    fn song_fields() {
+        let records = Records {
+            null: false,
+            fields: Some(parse_fields("file, title,album,type,missing")),
+        };
+        let song = Song {
+            file: "a/b\tc.flac".into(),
+            title: Some("So\nWhat".into()),
+            tags: vec![("Album".into(), "Kind of Blue".into())],
+            ..Song::default()
+        };
+        assert_eq!(
+            records.song_record(&song),
+            "a/b\\tc.flac\tSo\\nWhat\tKind of Blue\tsong\t"
+        );
+    }
+}
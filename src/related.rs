@@ -0,0 +1,271 @@
+use crate::ansi::{theme, FormattedString};
+use crate::error::Error;
+use crate::json;
+use crate::pick;
+use crate::raw;
+use crate::sort;
+use crate::table::{Align, Row, Table};
+use crate::tags::Tags;
+use mpdrs::error::Error as MpdError;
+use mpdrs::Song;
+use std::collections::HashMap;
+
+// The tags of the current song which link it to other music, and their
+// labels.
+const TAGS: [(&str, &str); 6] = [
+    ("artist", "Artist"),
+    ("albumartist", "Album artist"),
+    ("composer", "Composer"),
+    ("conductor", "Conductor"),
+    ("ensemble", "Ensemble"),
+    ("performer", "Performer"),
+];
+
+// A value shared with the current song, by one or more tags, and the albums
+// and works it appears on.
+struct Link {
+    tags: Vec<(&'static str, &'static str)>,
+    value: String,
+    items: Vec<Item>,
+}
+
+// An album, or a work on an album, with its songs in order.
+struct Item {
+    work: Option<String>,
+    composer: Option<String>,
+    // The album artist and album, as by sort::album.
+    album: (Option<String>, Option<String>),
+    songs: Vec<Song>,
+}
+
+impl Item {
+    fn name(&self) -> String {
+        let parts = match &self.work {
+            Some(work) => [Some(work), self.composer.as_ref()],
+            None => [self.album.1.as_ref(), self.album.0.as_ref()],
+        };
+        let parts = parts.into_iter().flatten().cloned().collect::<Vec<_>>();
+        parts.join(" - ")
+    }
+}
+
+// List the other albums and works which share an artist, composer, conductor,
+// ensemble or performer with the current song, or add the nth of them to the
+// queue.
+pub fn related(
+    client: &mut mpdrs::Client,
+    raw: &mut raw::Connection,
+    add: Option<usize>,
+    json: bool,
+) -> Result<(), Error> {
+    let Some(current) = client.currentsong()? else {
+        println!("Not playing.");
+        return Ok(());
+    };
+    let raw_comments = client
+        .readcomments(&current.file)?
+        .collect::<Result<Vec<_>, _>>()?;
+    let tags = Tags::from_song_and_raw_comments(&current, raw_comments);
+    let album = sort::album(&Tags::from_song(&current));
+
+    let mut links: Vec<Link> = vec![];
+    for (tag, label) in TAGS {
+        for value in tags.get(tag) {
+            // The same person is often both artist and album artist.
+            if let Some(link) = links.iter_mut().find(|l| l.value == value) {
+                link.tags.push((tag, label));
+                continue;
+            }
+            let songs = match raw.songs("find", &[tag, value]) {
+                Ok(songs) => songs,
+                // Such as a tag which MPD does not support.
+                Err(Error::Mpd(MpdError::Server(e))) => {
+                    log::trace!("Failed to find songs with {} {}: {}", tag, value, e);
+                    vec![]
+                }
+                Err(e) => return Err(e),
+            };
+            links.push(Link {
+                tags: vec![(tag, label)],
+                value: value.to_string(),
+                items: items(songs, &album),
+            });
+        }
+    }
+    links.retain(|link| !link.items.is_empty());
+
+    if let Some(n) = add {
+        let item = links
+            .iter()
+            .flat_map(|link| &link.items)
+            .nth(n.wrapping_sub(1));
+        match item {
+            Some(item) => pick::enqueue(client, raw, &item.songs, false)?,
+            None => println!("There is no related album or work number {}.", n),
+        }
+    } else if json {
+        print_json(&links);
+    } else if links.is_empty() {
+        println!("No related albums or works.");
+    } else {
+        print(&links);
+    }
+    Ok(())
+}
+
+// The songs grouped into works, or albums for songs without a work, except
+// those on the album of the current song. Songs with neither are left out.
+fn items(mut songs: Vec<Song>, current: &(Option<String>, Option<String>)) -> Vec<Item> {
+    sort::natural(&mut songs);
+    let mut items: Vec<Item> = vec![];
+    let mut index = HashMap::new();
+    for song in songs {
+        let tags = Tags::from_song(&song);
+        let album = sort::album(&tags);
+        let work = tags.get_option_joined("work");
+        if album == *current || (album.1.is_none() && work.is_none()) {
+            continue;
+        }
+        let composer = work.as_ref().and(tags.get_option_joined("composer"));
+        let key = (work.clone(), composer.clone(), album.clone());
+        let i = *index.entry(key).or_insert_with(|| {
+            items.push(Item {
+                work,
+                composer,
+                album,
+                songs: vec![],
+            });
+            items.len() - 1
+        });
+        items[i].songs.push(song);
+    }
+    for item in &mut items {
+        if item.work.is_some() {
+            sort::movements(&mut item.songs);
+        }
+    }
+    items
+}
+
+// Every link under a header, with its albums and works numbered for --add.
+// Works are shown with the album they are on.
+fn print(links: &[Link]) {
+    let mut number = 0;
+    for (i, link) in links.iter().enumerate() {
+        let labels = link.tags.iter().map(|(_, l)| *l).collect::<Vec<_>>();
+        let header = format!("{}: {}", labels.join(", "), link.value);
+        let fields = link
+            .items
+            .iter()
+            .map(|item| {
+                number += 1;
+                let album = match item.work {
+                    Some(_) => item.album.1.clone().unwrap_or_default(),
+                    None => String::new(),
+                };
+                (number.to_string(), item.name(), album)
+            })
+            .collect::<Vec<_>>();
+        let rows = fields
+            .iter()
+            .map(|(number, name, album)| {
+                Row::new(vec![
+                    FormattedString::new(number),
+                    FormattedString::new(name).style(theme().tag_value),
+                    FormattedString::new(album),
+                ])
+            })
+            .collect::<Vec<_>>();
+        if i > 0 {
+            println!();
+        }
+        println!(
+            "{}\n{}",
+            FormattedString::new(&header).style(theme().header),
+            Table::new(&rows).align(0, Align::Right)
+        );
+    }
+}
+
+fn print_json(links: &[Link]) {
+    let mut number = 0;
+    let links = links
+        .iter()
+        .map(|link| {
+            let tags = link.tags.iter().map(|(t, _)| (*t).into()).collect();
+            let items = link
+                .items
+                .iter()
+                .map(|item| {
+                    number += 1;
+                    let files = item.songs.iter().map(|s| s.file.as_str().into()).collect();
+                    json::Value::object(vec![
+                        ("number", json::Value::from(number as i64)),
+                        ("work", item.work.as_deref().into()),
+                        ("composer", item.composer.as_deref().into()),
+                        ("albumartist", item.album.0.as_deref().into()),
+                        ("album", item.album.1.as_deref().into()),
+                        ("files", json::Value::Array(files)),
+                    ])
+                })
+                .collect();
+            json::Value::object(vec![
+                ("tags", json::Value::Array(tags)),
+                ("value", link.value.as_str().into()),
+                ("items", json::Value::Array(items)),
+            ])
+        })
+        .collect();
+    println!("{}", json::Value::Array(links));
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn song(file: &str, tags: &[(&str, &str)]) -> Song {
+        Song {
+            file: file.into(),
+            tags: tags
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect(),
+            ..Song::default()
+        }
+    }
+
+    #[test]
+    fn grouping() {
+        let current = (Some("Karajan".to_string()), Some("Ninth".to_string()));
+        let songs = vec![
+            song("a", &[("AlbumArtist", "Karajan"), ("Album", "Ninth")]),
+            song(
+                "b2",
+                &[
+                    ("AlbumArtist", "Karajan"),
+                    ("Album", "Fifth"),
+                    ("Work", "Symphony No. 5"),
+                    ("Composer", "Beethoven"),
+                    ("MovementNumber", "2"),
+                ],
+            ),
+            song(
+                "b1",
+                &[
+                    ("AlbumArtist", "Karajan"),
+                    ("Album", "Fifth"),
+                    ("Work", "Symphony No. 5"),
+                    ("Composer", "Beethoven"),
+                    ("MovementNumber", "1"),
+                ],
+            ),
+            song("c", &[("Artist", "Karajan"), ("Album", "Live")]),
+            song("d", &[("Artist", "Karajan")]),
+        ];
+        let items = items(songs, &current);
+        let names = items.iter().map(Item::name).collect::<Vec<_>>();
+        assert_eq!(names, vec!["Symphony No. 5 - Beethoven", "Live - Karajan"]);
+        let files = items[0].songs.iter().map(|s| &*s.file).collect::<Vec<_>>();
+        assert_eq!(files, vec!["b1", "b2"]);
+    }
+}
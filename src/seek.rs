@@ -76,6 +76,7 @@ impl FromStr for Arg {
 }
 
 #[cfg(test)]
+#[allow(clippy::identity_op)]
 mod tests {
     use super::*;
 
@@ -0,0 +1,110 @@
+use crate::ansi::{theme, FormattedString};
+use crate::cli::SearchOptions;
+use crate::config::{Config, Smart};
+use crate::error::Error;
+use crate::json;
+use crate::random::Random;
+use crate::raw;
+use crate::sort;
+use crate::table::{Row, Table};
+use mpdrs::error::{Error as MpdError, ErrorCode};
+use mpdrs::Song;
+
+pub fn find<'a>(conf: &'a Config, name: &str) -> Result<&'a Smart, Error> {
+    conf.smart
+        .iter()
+        .find(|playlist| playlist.name == name)
+        .ok_or_else(|| Error::UnknownSmartPlaylist(name.to_string()))
+}
+
+// The songs exactly matching the query of the playlist, sorted by its sort
+// tag or in album order, then limited, and then sampled.
+pub fn songs(raw: &mut raw::Connection, playlist: &Smart) -> Result<Vec<Song>, Error> {
+    let options = SearchOptions {
+        sort: playlist.sort.clone(),
+        window: None,
+    };
+    let options_args = options.to_args();
+    let mut args = playlist.query.to_args();
+    args.extend(options_args.iter().map(String::as_str));
+    let mut songs = raw.songs("find", &args)?;
+    if playlist.sort.is_none() {
+        sort::natural(&mut songs);
+    }
+    if let Some(limit) = playlist.limit {
+        songs.truncate(limit);
+    }
+    if let Some(n) = playlist.random {
+        songs = Random::from_clock().sample(songs, n);
+    }
+    Ok(songs)
+}
+
+// Replace the stored playlist with the same name with the songs of the
+// playlist.
+pub fn save(raw: &mut raw::Connection, playlist: &Smart) -> Result<(), Error> {
+    let songs = songs(raw, playlist)?;
+    match raw.command("rm", &[&playlist.name]) {
+        Err(Error::Mpd(MpdError::Server(e))) if e.code == ErrorCode::NoExist => (),
+        result => {
+            result?;
+        }
+    }
+    let commands = songs
+        .iter()
+        .map(|song| ("playlistadd", vec![&*playlist.name, &*song.file]))
+        .collect::<Vec<_>>();
+    if !commands.is_empty() {
+        raw.command_list(&commands)?;
+    }
+    Ok(())
+}
+
+// The smart playlists of the config file, with their queries and options.
+pub fn list(conf: &Config, json: bool) {
+    if json {
+        let number = |n: Option<usize>| json::Value::from(n.map(|n| n as i64));
+        let playlists = conf
+            .smart
+            .iter()
+            .map(|playlist| {
+                json::Value::object(vec![
+                    ("name", playlist.name.as_str().into()),
+                    ("query", playlist.text.as_str().into()),
+                    ("sort", playlist.sort.as_deref().into()),
+                    ("limit", number(playlist.limit)),
+                    ("random", number(playlist.random)),
+                ])
+            })
+            .collect();
+        println!("{}", json::Value::Array(playlists));
+        return;
+    }
+
+    let fields = conf
+        .smart
+        .iter()
+        .map(|playlist| {
+            let options = [
+                playlist.sort.as_ref().map(|s| format!("sort {}", s)),
+                playlist.limit.map(|n| format!("limit {}", n)),
+                playlist.random.map(|n| format!("random {}", n)),
+            ];
+            let options = options.into_iter().flatten().collect::<Vec<_>>();
+            (&playlist.name, &playlist.text, options.join(", "))
+        })
+        .collect::<Vec<_>>();
+    let rows = fields
+        .iter()
+        .map(|(name, text, options)| {
+            Row::new(vec![
+                FormattedString::new(name).style(theme().tag_label),
+                FormattedString::new(text).style(theme().tag_value),
+                FormattedString::new(options),
+            ])
+        })
+        .collect::<Vec<_>>();
+    if !rows.is_empty() {
+        println!("{}", Table::new(&rows));
+    }
+}
@@ -0,0 +1,113 @@
+use crate::tags::Tags;
+use mpdrs::Song;
+use std::collections::HashMap;
+
+// Sort songs by disc and track number, numerically, within each album. Albums
+// stay in the order in which they first appear, and songs without numbers
+// keep their relative order.
+pub fn natural(songs: &mut [Song]) {
+    let mut albums = HashMap::new();
+    let keys = songs
+        .iter()
+        .map(|song| {
+            let tags = Tags::from_song(song);
+            let next = albums.len();
+            let album = *albums.entry(album(&tags)).or_insert(next);
+            (
+                album,
+                tags.get_option_joined("disc").and_then(|d| number(&d)),
+                tags.get_option_joined("track").and_then(|t| number(&t)),
+            )
+        })
+        .collect::<Vec<_>>();
+    let mut order = (0..songs.len()).collect::<Vec<_>>();
+    order.sort_by_key(|i| keys[*i]);
+    let sorted = order.iter().map(|i| songs[*i].clone()).collect::<Vec<_>>();
+    songs.clone_from_slice(&sorted);
+}
+
+// The album artist, or artist if there is none, and album of a song, which
+// identify its album.
+pub fn album(tags: &Tags) -> (Option<String>, Option<String>) {
+    (
+        tags.get_option_joined("albumartist")
+            .or_else(|| tags.get_option_joined("artist")),
+        tags.get_option_joined("album"),
+    )
+}
+
+// Sort songs by movement number, keeping songs without one last, in their
+// relative order.
+pub fn movements(songs: &mut [Song]) {
+    songs.sort_by_key(|song| {
+        let movement = Tags::from_song(song)
+            .get_option_joined("movementnumber")
+            .and_then(|m| number(&m));
+        (movement.is_none(), movement)
+    });
+}
+
+// The leading number of a tag such as "3" or "3/12".
+pub fn number(s: &str) -> Option<u32> {
+    let digits = s.trim().split(|c: char| !c.is_ascii_digit()).next()?;
+    digits.parse().ok()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn song(file: &str, album: &str, disc: &str, track: &str) -> Song {
+        Song {
+            file: file.into(),
+            tags: vec![
+                ("Album".into(), album.into()),
+                ("Disc".into(), disc.into()),
+                ("Track".into(), track.into()),
+            ],
+            ..Song::default()
+        }
+    }
+
+    #[test]
+    fn numbers() {
+        assert_eq!(number("3"), Some(3));
+        assert_eq!(number(" 03/12"), Some(3));
+        assert_eq!(number("x"), None);
+    }
+
+    #[test]
+    fn natural_order() {
+        let mut songs = vec![
+            song("b10", "B", "1", "10"),
+            song("a2", "A", "1", "2/12"),
+            song("b2", "B", "1", "2"),
+            song("b1-disc2", "B", "2", "1"),
+            song("a1", "A", "1", "1/12"),
+        ];
+        natural(&mut songs);
+        let files = songs.iter().map(|s| &*s.file).collect::<Vec<_>>();
+        assert_eq!(files, vec!["b2", "b10", "b1-disc2", "a1", "a2"]);
+    }
+
+    #[test]
+    fn movement_order() {
+        let movement = |file: &str, number: &str| Song {
+            file: file.into(),
+            tags: vec![("MovementNumber".into(), number.into())],
+            ..Song::default()
+        };
+        let mut songs = vec![
+            movement("iii", "3"),
+            Song {
+                file: "none".into(),
+                ..Song::default()
+            },
+            movement("i", "1"),
+            movement("ii", "2"),
+        ];
+        movements(&mut songs);
+        let files = songs.iter().map(|s| &*s.file).collect::<Vec<_>>();
+        assert_eq!(files, vec!["i", "ii", "iii", "none"]);
+    }
+}
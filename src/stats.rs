@@ -0,0 +1,228 @@
+use crate::ansi::{theme, FormattedString};
+use crate::cli::SearchQuery;
+use crate::error::Error;
+use crate::json;
+use crate::raw;
+use crate::status::duration_format;
+use crate::table::{Align, Row, Table};
+use std::time::Duration;
+
+pub fn stats(client: &mut mpdrs::Client, json: bool) -> Result<(), Error> {
+    let stats = client.stats()?;
+
+    if json {
+        let value = json::Value::object(vec![
+            ("artists", stats.artists.into()),
+            ("albums", stats.albums.into()),
+            ("songs", stats.songs.into()),
+            ("uptime", stats.uptime.as_secs_f64().into()),
+            ("playtime", stats.playtime.as_secs_f64().into()),
+            ("db_playtime", stats.db_playtime.as_secs_f64().into()),
+            ("db_update", stats.db_update.as_secs_f64().into()),
+        ]);
+        println!("{}", value);
+        return Ok(());
+    }
+
+    print_key_values(&[
+        ("Artists", stats.artists.to_string()),
+        ("Albums", stats.albums.to_string()),
+        ("Songs", stats.songs.to_string()),
+        ("Uptime", long_duration_format(&stats.uptime)),
+        ("Playtime", long_duration_format(&stats.playtime)),
+        ("DB playtime", long_duration_format(&stats.db_playtime)),
+        ("DB updated", local_time_format(&stats.db_update)),
+    ]);
+    Ok(())
+}
+
+struct Count {
+    group: Option<String>,
+    songs: u32,
+    playtime: Duration,
+}
+
+// Print the number of songs and their total duration matching query, for
+// every value of the group tag, longest first.
+pub fn count(
+    raw: &mut raw::Connection,
+    query: &SearchQuery,
+    group: Option<&str>,
+    json: bool,
+) -> Result<(), Error> {
+    let mut args = query.to_args();
+    if let Some(group) = group {
+        args.extend(["group", group]);
+    }
+    let mut counts = parse_counts(raw.command("count", &args)?);
+    counts.sort_by_key(|c| std::cmp::Reverse(c.playtime));
+
+    if json {
+        let counts = counts
+            .iter()
+            .map(|c| {
+                json::Value::object(vec![
+                    ("group", c.group.clone().into()),
+                    ("songs", c.songs.into()),
+                    ("playtime", c.playtime.as_secs_f64().into()),
+                ])
+            })
+            .collect();
+        println!("{}", json::Value::Array(counts));
+        return Ok(());
+    }
+
+    if group.is_none() {
+        let songs = counts.first().map_or(0, |c| c.songs);
+        let playtime = counts.first().map_or(Duration::ZERO, |c| c.playtime);
+        print_key_values(&[
+            ("Songs", songs.to_string()),
+            ("Playtime", long_duration_format(&playtime)),
+        ]);
+        return Ok(());
+    }
+
+    let fields = counts
+        .iter()
+        .map(|c| {
+            (
+                c.group.clone().unwrap_or_default(),
+                c.songs.to_string(),
+                long_duration_format(&c.playtime),
+            )
+        })
+        .collect::<Vec<_>>();
+    let table_rows = fields
+        .iter()
+        .map(|(name, songs, playtime)| {
+            Row::new(vec![
+                FormattedString::new(name).style(theme().tag_label),
+                FormattedString::new(songs).style(theme().tag_value),
+                FormattedString::new(playtime).style(theme().tag_value),
+            ])
+        })
+        .collect::<Vec<_>>();
+    println!(
+        "{}",
+        Table::new(&table_rows)
+            .align(1, Align::Right)
+            .align(2, Align::Right)
+    );
+    Ok(())
+}
+
+pub fn print_key_values(rows: &[(&str, String)]) {
+    let table_rows = rows
+        .iter()
+        .map(|(key, val)| {
+            Row::new(vec![
+                FormattedString::new(key).style(theme().tag_label),
+                FormattedString::new(val).style(theme().tag_value),
+            ])
+        })
+        .collect::<Vec<_>>();
+    println!("{}", Table::new(&table_rows));
+}
+
+// The response to count is a list of songs and playtime pairs, each preceded
+// by the value of the group tag, if grouped.
+fn parse_counts(pairs: Vec<(String, String)>) -> Vec<Count> {
+    let mut counts = vec![];
+    let mut current = None;
+    for (key, value) in pairs {
+        match &*key {
+            "songs" | "playtime" => {
+                let count = current.get_or_insert(Count {
+                    group: None,
+                    songs: 0,
+                    playtime: Duration::ZERO,
+                });
+                if key == "songs" {
+                    count.songs = value.parse().unwrap_or_default();
+                } else {
+                    count.playtime = Duration::from_secs(value.parse().unwrap_or_default());
+                }
+            }
+            _ => {
+                counts.extend(current.take());
+                current = Some(Count {
+                    group: Some(value),
+                    songs: 0,
+                    playtime: Duration::ZERO,
+                });
+            }
+        }
+    }
+    counts.extend(current);
+    counts
+}
+
+// Format as h:mm:ss, prefixed by the number of days if any.
+fn long_duration_format(d: &Duration) -> String {
+    let days = d.as_secs() / 86400;
+    let rest = Duration::from_secs(d.as_secs() % 86400);
+    let time = duration_format(&rest);
+    match days {
+        0 => time,
+        1 => format!("1 day, {}", time),
+        days => format!("{} days, {}", days, time),
+    }
+}
+
+// Format seconds since the epoch as local time.
+fn local_time_format(since_epoch: &Duration) -> String {
+    let time = since_epoch.as_secs() as libc::time_t;
+    let mut tm: libc::tm = unsafe { std::mem::zeroed() };
+    if unsafe { libc::localtime_r(&time, &mut tm) }.is_null() {
+        return since_epoch.as_secs().to_string();
+    }
+    format!(
+        "{}-{:02}-{:02} {:02}:{:02}:{:02}",
+        tm.tm_year + 1900,
+        tm.tm_mon + 1,
+        tm.tm_mday,
+        tm.tm_hour,
+        tm.tm_min,
+        tm.tm_sec
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn pairs(p: &[(&str, &str)]) -> Vec<(String, String)> {
+        p.iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn counts() {
+        let counts = parse_counts(pairs(&[("songs", "3"), ("playtime", "120")]));
+        assert_eq!(counts.len(), 1);
+        assert_eq!(counts[0].group, None);
+        assert_eq!(counts[0].songs, 3);
+
+        let counts = parse_counts(pairs(&[
+            ("AlbumArtist", "A"),
+            ("songs", "3"),
+            ("playtime", "120"),
+            ("AlbumArtist", ""),
+            ("songs", "1"),
+            ("playtime", "60"),
+        ]));
+        assert_eq!(counts.len(), 2);
+        assert_eq!(counts[1].group.as_deref(), Some(""));
+        assert_eq!(counts[1].playtime, Duration::from_secs(60));
+    }
+
+    #[test]
+    fn long_durations() {
+        assert_eq!(long_duration_format(&Duration::from_secs(65)), "01:05");
+        assert_eq!(
+            long_duration_format(&Duration::from_secs(2 * 86400 + 3725)),
+            "2 days, 1:02:05"
+        );
+    }
+}
@@ -1,64 +1,142 @@
-use crate::ansi::{FormattedString, Style};
+use crate::ansi::{theme, FormattedString, Style};
 use crate::error::Error;
+use crate::follow::{self, View as _};
+use crate::json;
+use crate::progress::Progress;
 use crate::table::{Row, Table};
 use std::time::Duration;
 
-pub fn status(c: &mut mpdrs::Client) -> Result<(), Error> {
-    let song = c.currentsong()?;
-    let status = c.status()?;
+pub fn status(c: &mut mpdrs::Client, json: bool) -> Result<(), Error> {
+    let mut view = View::default();
+    view.update(c)?;
 
-    let mut table_rows = vec![];
+    if json {
+        println!("{}", to_json(&view.status, view.song.as_ref()));
+        return Ok(());
+    }
+
+    print!("{}", view.render(Duration::ZERO));
+    Ok(())
+}
+
+#[derive(Default)]
+pub struct View {
+    song: Option<mpdrs::Song>,
+    status: mpdrs::Status,
+}
 
-    if let Some(song) = song.as_ref() {
-        table_rows.push(table_row("Song", &*song.file));
+impl follow::View for View {
+    fn subsystems(&self) -> &'static [&'static str] {
+        &["player", "mixer", "options", "update"]
     }
 
-    let update_status = status
-        .updating_db
-        .map(|update_id| format!("DB update #{} in progress.", update_id));
-    if let Some(status) = update_status.as_ref() {
-        table_rows.push(table_row("Update", &*status));
+    fn update(&mut self, c: &mut mpdrs::Client) -> Result<(), Error> {
+        self.song = c.currentsong()?;
+        self.status = c.status()?;
+        Ok(())
+    }
+
+    fn render(&self, since_update: Duration) -> String {
+        let status = follow::advance(&self.status, since_update);
+        let mut table_rows = vec![];
+
+        if let Some(song) = self.song.as_ref() {
+            table_rows.push(table_row("Song", &song.file));
+        }
+
+        let update_status = status
+            .updating_db
+            .map(|update_id| format!("DB update #{} in progress.", update_id));
+        if let Some(status) = update_status.as_ref() {
+            table_rows.push(table_row("Update", status));
+        }
+
+        let progress = Progress::from_status(&status);
+        let time = progress.as_ref().map(Progress::time);
+        if let Some(time) = time.as_ref() {
+            table_rows.push(styled_table_row("Time", time, theme().progress));
+        }
+
+        let (state, style) = match status.state {
+            mpdrs::State::Play => ("playing", theme().playing),
+            mpdrs::State::Pause => ("paused", theme().paused),
+            mpdrs::State::Stop => ("Stopped", theme().stopped),
+        };
+        table_rows.push(styled_table_row("State", state, style));
+
+        let queue_position = status.song.map(|s| format!("{}", 1 + s.pos));
+        if let Some(pos) = queue_position.as_ref() {
+            table_rows.push(table_row("Position", pos));
+        }
+        let volume = format!("{}%", status.volume);
+        table_rows.push(table_row("Volume", &volume));
+        table_rows.push(table_row("Repeat", bool_on_off(status.repeat)));
+        table_rows.push(table_row("Random", bool_on_off(status.random)));
+        table_rows.push(table_row("Single", bool_on_off(status.single)));
+        table_rows.push(table_row("Consume", bool_on_off(status.consume)));
+        let mut out = format!("{}\n", Table::new(&table_rows));
+        if let Some(bar) = progress.and_then(|p| p.bar()) {
+            out.push_str(&format!("{}\n", bar));
+        }
+        out
     }
 
-    let time = status.time.map(|(current, total)| {
-        format!(
-            "{}/{} ({:2.0}%)",
-            duration_format(&current),
-            duration_format(&total),
-            (100. * current.as_secs_f64() / total.as_secs_f64()).trunc()
-        )
-    });
-
-    if let Some(time) = time.as_ref() {
-        table_rows.push(table_row("Time", &*time));
+    fn title(&self) -> Option<String> {
+        let state = match self.status.state {
+            mpdrs::State::Play => "playing",
+            mpdrs::State::Pause => "paused",
+            mpdrs::State::Stop => "stopped",
+        };
+        Some(format!("MPD {}", state))
     }
+}
 
+fn to_json(status: &mpdrs::Status, song: Option<&mpdrs::Song>) -> json::Value {
     let state = match status.state {
-        mpdrs::State::Play => "playing",
-        mpdrs::State::Pause => "paused",
-        mpdrs::State::Stop => "Stopped",
+        mpdrs::State::Play => "play",
+        mpdrs::State::Pause => "pause",
+        mpdrs::State::Stop => "stop",
     };
-    table_rows.push(table_row("State", state));
-
-    let queue_position = status.song.map(|s| format!("{}", 1 + s.pos));
-    if let Some(pos) = queue_position.as_ref() {
-        table_rows.push(table_row("Position", &*pos));
-    }
-    let volume = format!("{}%", status.volume);
-    table_rows.push(table_row("Volume", &*volume));
-    table_rows.push(table_row("Repeat", bool_on_off(status.repeat)));
-    table_rows.push(table_row("Random", bool_on_off(status.random)));
-    table_rows.push(table_row("Single", bool_on_off(status.single)));
-    table_rows.push(table_row("Consume", bool_on_off(status.consume)));
-    println!("{}", Table { rows: &table_rows });
-    Ok(())
+    let elapsed = status
+        .elapsed
+        .or_else(|| status.time.map(|(elapsed, _)| elapsed));
+    let duration = status
+        .duration
+        .or_else(|| status.time.map(|(_, total)| total))
+        .filter(|d| !d.is_zero());
+    let volume = Some(status.volume)
+        .filter(|v| *v >= 0)
+        .map(|v| u32::from(v.unsigned_abs()));
+    json::Value::object(vec![
+        ("state", state.into()),
+        ("song", song.map_or(json::Value::Null, json::song)),
+        ("position", status.song.map(|s| s.pos + 1).into()),
+        ("song_id", status.song.map(|s| s.id).into()),
+        ("next_position", status.nextsong.map(|s| s.pos + 1).into()),
+        ("next_song_id", status.nextsong.map(|s| s.id).into()),
+        ("elapsed", elapsed.map(|d| d.as_secs_f64()).into()),
+        ("duration", duration.map(|d| d.as_secs_f64()).into()),
+        ("volume", volume.into()),
+        ("repeat", status.repeat.into()),
+        ("random", status.random.into()),
+        ("single", status.single.into()),
+        ("consume", status.consume.into()),
+        ("queue_length", status.queue_len.into()),
+        ("bitrate", status.bitrate.into()),
+        ("updating_db", status.updating_db.into()),
+        ("error", status.error.clone().into()),
+    ])
 }
 
-// Table row with bold key and normal value
+// Table row with the key styled as a tag label, and the value as a tag value
 fn table_row<'a>(key: &'a str, val: &'a str) -> Row<'a> {
+    styled_table_row(key, val, theme().tag_value)
+}
+
+fn styled_table_row<'a>(key: &'a str, val: &'a str, style: Style) -> Row<'a> {
     Row::new(vec![
-        FormattedString::new(key).style(Style::Bold),
-        FormattedString::new(val),
+        FormattedString::new(key).style(theme().tag_label),
+        FormattedString::new(val).style(style),
     ])
 }
 
@@ -70,6 +148,12 @@ fn bool_on_off(b: bool) -> &'static str {
     }
 }
 
-fn duration_format(d: &Duration) -> String {
-    format!("{:02}:{:02}", d.as_secs() / 60, d.as_secs() % 60)
+// Format as mm:ss, or h:mm:ss for durations of an hour or more.
+pub fn duration_format(d: &Duration) -> String {
+    let secs = d.as_secs();
+    if secs >= 3600 {
+        format!("{}:{:02}:{:02}", secs / 3600, secs / 60 % 60, secs % 60)
+    } else {
+        format!("{:02}:{:02}", secs / 60, secs % 60)
+    }
 }
@@ -0,0 +1,309 @@
+use crate::ansi::{theme, FormattedString};
+use crate::error::Error;
+use crate::follow;
+use crate::json;
+use crate::raw;
+use crate::table::{Align, Row, Table};
+use mpdrs::error::{Error as MpdError, ErrorCode};
+use mpdrs::{Song, State};
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+// The sticker set by rate, from 1 to 5.
+pub const RATING: &str = "rating";
+// The sticker incremented by count-plays.
+const PLAYCOUNT: &str = "playcount";
+
+// A song counts as played to its end if the player moved on at most this long
+// before the end, which leaves room for crossfading.
+const END: Duration = Duration::from_secs(10);
+
+pub enum Action {
+    Get {
+        path: String,
+        name: String,
+    },
+    Set {
+        path: String,
+        name: String,
+        value: String,
+    },
+    // Delete one sticker, or all stickers of the song if name is None.
+    Delete {
+        path: String,
+        name: Option<String>,
+    },
+    List {
+        path: String,
+    },
+    // The songs below path with the sticker, optionally only those where it
+    // equals value.
+    Find {
+        name: String,
+        path: String,
+        value: Option<String>,
+    },
+    CountPlays,
+}
+
+pub fn sticker(
+    client: &mut mpdrs::Client,
+    raw: &mut raw::Connection,
+    action: Action,
+    json: bool,
+) -> Result<(), Error> {
+    match action {
+        Action::Get { path, name } => {
+            let value = client.sticker("song", &path, &name)?;
+            if json {
+                println!("{}", json::Value::from(value));
+            } else {
+                println!("{}", value);
+            }
+        }
+        Action::Set { path, name, value } => client.set_sticker("song", &path, &name, &value)?,
+        Action::Delete {
+            path,
+            name: Some(name),
+        } => client.delete_sticker("song", &path, &name)?,
+        Action::Delete { path, name: None } => client.clear_stickers("song", &path)?,
+        Action::List { path } => {
+            let mut stickers = client
+                .stickers_map("song", &path)?
+                .into_iter()
+                .collect::<Vec<_>>();
+            stickers.sort();
+            if json {
+                let fields = stickers
+                    .into_iter()
+                    .map(|(name, value)| (name, value.into()))
+                    .collect();
+                println!("{}", json::Value::object(fields));
+                return Ok(());
+            }
+            let rows = stickers
+                .iter()
+                .map(|(name, value)| {
+                    Row::new(vec![
+                        FormattedString::new(name).style(theme().tag_label),
+                        FormattedString::new(value).style(theme().tag_value),
+                    ])
+                })
+                .collect::<Vec<_>>();
+            if !rows.is_empty() {
+                println!("{}", Table::new(&rows));
+            }
+        }
+        Action::Find { name, path, value } => {
+            let mut args = vec!["find", "song", &path, &name];
+            if let Some(value) = &value {
+                args.extend(["=", value]);
+            }
+            let found = raw::split(raw.command("sticker", &args)?, &["file"])
+                .into_iter()
+                .filter_map(|group| {
+                    let value = group
+                        .iter()
+                        .find(|(k, _)| k == "sticker")
+                        .and_then(|(_, v)| v.split_once('='))
+                        .map(|(_, v)| v.to_string())?;
+                    Some((group[0].1.clone(), value))
+                })
+                .collect::<Vec<_>>();
+            print_found(&found, json);
+        }
+        Action::CountPlays => count_plays(client, raw)?,
+    }
+    Ok(())
+}
+
+// Print files and sticker values, as a table of the value and the file.
+fn print_found(found: &[(String, String)], json: bool) {
+    if json {
+        let found = found
+            .iter()
+            .map(|(file, value)| {
+                json::Value::object(vec![
+                    ("file", file.as_str().into()),
+                    ("value", value.as_str().into()),
+                ])
+            })
+            .collect();
+        println!("{}", json::Value::Array(found));
+        return;
+    }
+    let rows = found
+        .iter()
+        .map(|(file, value)| {
+            Row::new(vec![
+                FormattedString::new(value).style(theme().tag_value),
+                FormattedString::new(file),
+            ])
+        })
+        .collect::<Vec<_>>();
+    if !rows.is_empty() {
+        println!("{}", Table::new(&rows).align(0, Align::Right));
+    }
+}
+
+// Rate the current song from 1 to 5, or remove its rating with 0.
+pub fn rate(client: &mut mpdrs::Client, rating: u8) -> Result<(), Error> {
+    let Some(song) = client.currentsong()? else {
+        println!("Not playing.");
+        return Ok(());
+    };
+    if rating > 0 {
+        client.set_sticker("song", &song.file, RATING, &rating.to_string())?;
+        return Ok(());
+    }
+    match client.delete_sticker("song", &song.file, RATING) {
+        Err(MpdError::Server(e)) if e.code == ErrorCode::NoExist => Ok(()),
+        result => Ok(result?),
+    }
+}
+
+// The stickers of a song, sorted by name. Songs which are not in the
+// database, such as streams, have none, and neither has any song if MPD runs
+// without a sticker database.
+pub fn stickers(client: &mut mpdrs::Client, file: &str) -> Result<Vec<(String, String)>, Error> {
+    match client.stickers_map("song", file) {
+        Ok(stickers) => {
+            let mut stickers = stickers.into_iter().collect::<Vec<_>>();
+            stickers.sort();
+            Ok(stickers)
+        }
+        Err(MpdError::Server(e)) => {
+            log::trace!("Failed to read the stickers of {}: {}", file, e);
+            Ok(vec![])
+        }
+        Err(e) => Err(e.into()),
+    }
+}
+
+// The songs rated at least min.
+pub fn rated(client: &mut mpdrs::Client, songs: Vec<Song>, min: u8) -> Result<Vec<Song>, Error> {
+    let ratings = client
+        .find_sticker("song", "", RATING)?
+        .into_iter()
+        .filter_map(|(file, rating)| Some((file, rating.parse::<u8>().ok()?)))
+        .collect::<HashMap<_, _>>();
+    Ok(songs
+        .into_iter()
+        .filter(|song| ratings.get(&song.file).is_some_and(|r| *r >= min))
+        .collect())
+}
+
+// Increment the playcount sticker of every song which is played to its end,
+// until interrupted.
+fn count_plays(client: &mut mpdrs::Client, raw: &mut raw::Connection) -> Result<(), Error> {
+    let mut file = client.currentsong()?.map(|s| s.file);
+    let mut status = client.status()?;
+    let mut updated = Instant::now();
+    loop {
+        if raw.idle(&["player"], Duration::from_secs(3600))?.is_none() {
+            continue;
+        }
+        let next = client.status()?;
+        if let Some(file) = file.filter(|_| finished(&status, &next, updated.elapsed())) {
+            increment(client, &file)?;
+        }
+        file = client.currentsong()?.map(|s| s.file);
+        status = next;
+        updated = Instant::now();
+    }
+}
+
+// Whether the song playing in before, since when it was read, was played to
+// its end when the status changed to after: it was close to its end, and
+// either another song is playing, or it started over.
+fn finished(before: &mpdrs::Status, after: &mpdrs::Status, since: Duration) -> bool {
+    if before.state != State::Play {
+        return false;
+    }
+    let before = follow::advance(before, since);
+    let elapsed = before.elapsed.or(before.time.map(|t| t.0));
+    let duration = before.duration.or(before.time.map(|t| t.1));
+    let (Some(elapsed), Some(duration)) = (elapsed, duration) else {
+        return false;
+    };
+    let song = |status: &mpdrs::Status| status.song.map(|s| s.id);
+    let restarted = after.elapsed.unwrap_or_default() + END < elapsed;
+    elapsed + END >= duration && (song(&before) != song(after) || restarted)
+}
+
+fn increment(client: &mut mpdrs::Client, file: &str) -> Result<(), Error> {
+    let count = match client.sticker("song", file, PLAYCOUNT) {
+        Ok(count) => count.parse::<u64>().unwrap_or(0),
+        Err(MpdError::Server(e)) if e.code == ErrorCode::NoExist => 0,
+        Err(e) => return Err(e.into()),
+    };
+    log::trace!("Played {} to the end, {} plays.", file, count + 1);
+    match client.set_sticker("song", file, PLAYCOUNT, &(count + 1).to_string()) {
+        // Such as a stream, which is not in the database.
+        Err(MpdError::Server(e)) => {
+            log::warn!("Failed to count the play of {}: {}", file, e);
+            Ok(())
+        }
+        result => Ok(result?),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use mpdrs::song::QueuePlace;
+
+    fn status(state: State, id: u32, elapsed: u64) -> mpdrs::Status {
+        mpdrs::Status {
+            state,
+            song: Some(QueuePlace {
+                id,
+                ..QueuePlace::default()
+            }),
+            elapsed: Some(Duration::from_secs(elapsed)),
+            duration: Some(Duration::from_secs(300)),
+            ..mpdrs::Status::default()
+        }
+    }
+
+    #[test]
+    fn finished_songs() {
+        let second = Duration::from_secs(1);
+        // The next song started at the end of the previous one.
+        assert!(finished(
+            &status(State::Play, 1, 295),
+            &status(State::Play, 2, 0),
+            second
+        ));
+        // The last song of the queue ended.
+        assert!(finished(
+            &status(State::Play, 1, 290),
+            &mpdrs::Status::default(),
+            Duration::from_secs(10)
+        ));
+        // Repeated with single mode.
+        assert!(finished(
+            &status(State::Play, 1, 299),
+            &status(State::Play, 1, 0),
+            second
+        ));
+        // Skipped.
+        assert!(!finished(
+            &status(State::Play, 1, 100),
+            &status(State::Play, 2, 0),
+            second
+        ));
+        // Paused near the end.
+        assert!(!finished(
+            &status(State::Play, 1, 295),
+            &status(State::Pause, 1, 296),
+            second
+        ));
+        // Skipped while paused near the end.
+        assert!(!finished(
+            &status(State::Pause, 1, 295),
+            &status(State::Play, 2, 0),
+            second
+        ));
+    }
+}
@@ -33,7 +33,7 @@ pub fn find_subcommand(name: &ffi::OsStr) -> Option<PathBuf> {
 }
 
 // copied from https://github.com/frewsxcv/rust-quale
-static EXECUTABLE_FLAGS: u32 = (libc::S_IEXEC | libc::S_IXGRP | libc::S_IXOTH) as u32;
+static EXECUTABLE_FLAGS: u32 = libc::S_IEXEC | libc::S_IXGRP | libc::S_IXOTH;
 fn is_executable(file: &fs::DirEntry) -> bool {
     // Don't use `file.metadata()` directly since it doesn't follow symlinks.
     let file_metadata = match file.path().metadata() {
@@ -1,21 +1,28 @@
-use crate::error::Error;
+use crate::records::Records;
 use mpdrs::lsinfo::LsInfoResponse;
 
-pub fn complete(client: &mut mpdrs::Client, search_path: &str) -> Result<(), Error> {
-    let prefix_path = match search_path.rfind('/') {
+// The directory whose entries complete search_path.
+pub fn directory(search_path: &str) -> &str {
+    match search_path.rfind('/') {
         Some(i) => &search_path[..i],
         None => "",
-    };
-    for item in client.lsinfo(prefix_path)? {
-        match item {
-            LsInfoResponse::Song(s) if s.file.starts_with(search_path) => println!("{}", s.file),
-            LsInfoResponse::Playlist { path, .. } | LsInfoResponse::Directory { path, .. }
-                if path.starts_with(search_path) =>
-            {
-                println!("{}", path);
-            }
-            _ => (),
+    }
+}
+
+// Complete search_path with the entries of its directory, from lsinfo of the
+// directory, or the whole database as in the cache.
+pub fn complete(entries: &[LsInfoResponse], search_path: &str, records: &Records) {
+    let prefix_path = directory(search_path);
+    let mut matches = vec![];
+    for entry in entries {
+        let path = match entry {
+            LsInfoResponse::Song(s) => &s.file,
+            LsInfoResponse::Playlist { path, .. } | LsInfoResponse::Directory { path, .. } => path,
+        };
+        let parent = path.rfind('/').map_or("", |i| &path[..i]);
+        if parent == prefix_path && path.starts_with(search_path) {
+            matches.push(path.clone());
         }
     }
-    Ok(())
+    records.print_values(&matches);
 }
@@ -1,5 +1,5 @@
-use crate::ansi::{is_dumb_terminal, FormattedString};
-use std::collections::HashMap;
+use crate::ansi::{is_dumb_terminal, terminal_width, FormattedString};
+use crate::unicode;
 use std::fmt;
 
 pub struct Row<'a> {
@@ -12,34 +12,99 @@ impl<'a> Row<'a> {
     }
 }
 
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Align {
+    Left,
+    Right,
+}
+
+// A table with one column per field, aligned by display width. The last
+// column is truncated to fit in the terminal, or in the width given to the
+// formatter, as in format!("{:80}", table).
 pub struct Table<'a> {
-    pub rows: &'a [Row<'a>],
+    rows: &'a [Row<'a>],
+    align: Vec<Align>,
+}
+
+impl<'a> Table<'a> {
+    pub fn new(rows: &'a [Row<'a>]) -> Table<'a> {
+        Table {
+            rows,
+            align: vec![],
+        }
+    }
+
+    pub fn align(mut self, column: usize, align: Align) -> Table<'a> {
+        if self.align.len() <= column {
+            self.align.resize(column + 1, Align::Left);
+        }
+        self.align[column] = align;
+        self
+    }
+}
+
+fn pad(s: &str, width: usize, align: Align) -> String {
+    let padding = " ".repeat(width.saturating_sub(unicode::width(s)));
+    match align {
+        Align::Left => format!("{}{}", s, padding),
+        Align::Right => format!("{}{}", padding, s),
+    }
 }
 
 impl<'a> fmt::Display for Table<'a> {
     fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
-        let widths = self
-            .rows
-            .iter()
-            .flat_map(|Row { fields }| fields.iter().enumerate().map(|(i, f)| (i, f.string.len())))
-            .fold(HashMap::<usize, usize>::new(), |mut m, (i, len)| {
-                m.entry(i)
-                    .and_modify(|cur| *cur = (*cur).max(len))
-                    .or_insert(len);
-                m
-            });
+        let mut widths = vec![];
+        for Row { fields } in self.rows {
+            for (i, f) in fields.iter().enumerate() {
+                let width = unicode::width(f.string);
+                match widths.get_mut(i) {
+                    Some(w) => *w = width.max(*w),
+                    None => widths.push(width),
+                }
+            }
+        }
+        let max_width = if is_dumb_terminal() {
+            None
+        } else {
+            formatter.width().or_else(terminal_width)
+        };
 
         for (i, Row { fields }) in self.rows.iter().enumerate() {
             if i != 0 {
                 writeln!(formatter)?;
             }
+            let mut used = 0;
             for (i, f) in fields.iter().enumerate() {
+                let align = self.align.get(i).copied().unwrap_or(Align::Left);
                 if i + 1 == fields.len() {
-                    write!(formatter, "{}", f)?;
+                    let mut string = match max_width {
+                        Some(max) => unicode::truncate(f.string, max.saturating_sub(used)),
+                        None => f.string.to_string(),
+                    };
+                    if align == Align::Right {
+                        string = pad(&string, widths[i], align);
+                    }
+                    write!(
+                        formatter,
+                        "{}",
+                        FormattedString {
+                            string: &string,
+                            ..*f
+                        }
+                    )?;
                 } else if is_dumb_terminal() {
                     write!(formatter, "{}:", f)?;
                 } else {
-                    write!(formatter, "{:width$}", f, width = widths[&i] + 1)?;
+                    let string = pad(f.string, widths[i], align) + " ";
+                    used += widths[i] + 1;
+                    write!(
+                        formatter,
+                        "{}",
+                        FormattedString {
+                            string: &string,
+                            ..*f
+                        }
+                    )?;
                 }
             }
         }
@@ -59,7 +124,7 @@ mod test {
         let key2 = FormattedString::new("key");
         let val2 = FormattedString::new("val");
         let rows = [Row::new(vec![key1, val1]), Row::new(vec![key2, val2])];
-        let table = Table { rows: &rows };
+        let table = Table::new(&rows);
         let result = format!("{:100}", table);
         let expected = "long_key val\n\
                         key      val";
@@ -68,15 +133,67 @@ mod test {
 
     #[test]
     fn basic_functionality_with_formatting() {
-        let key1 = FormattedString::new("long_key").style(Style::Faint);
-        let val1 = FormattedString::new("val").style(Style::Bold);
-        let key2 = FormattedString::new("key").style(Style::Faint);
-        let val2 = FormattedString::new("val").style(Style::Bold);
+        let key1 = FormattedString::new("long_key").style(Style::FAINT);
+        let val1 = FormattedString::new("val").style(Style::BOLD);
+        let key2 = FormattedString::new("key").style(Style::FAINT);
+        let val2 = FormattedString::new("val").style(Style::BOLD);
         let rows = [Row::new(vec![key1, val1]), Row::new(vec![key2, val2])];
-        let table = Table { rows: &rows };
-        let result = format!("{}", table);
+        let table = Table::new(&rows);
+        let result = format!("{:100}", table);
         let expected = "\x1B[2mlong_key \x1B[0m\x1B[1mval\x1B[0m\n\
                         \x1B[2mkey      \x1B[0m\x1B[1mval\x1B[0m";
         assert_eq!(&*result, expected);
     }
+
+    #[test]
+    fn wide_characters() {
+        let rows = [
+            Row::new(vec![
+                FormattedString::new("宇多田"),
+                FormattedString::new("a"),
+            ]),
+            Row::new(vec![FormattedString::new("abc"), FormattedString::new("b")]),
+            Row::new(vec![
+                FormattedString::new("e\u{301}"),
+                FormattedString::new("c"),
+            ]),
+        ];
+        let result = format!("{:100}", Table::new(&rows));
+        let expected = "宇多田 a\n\
+                        abc    b\n\
+                        e\u{301}      c";
+        assert_eq!(&*result, expected);
+    }
+
+    #[test]
+    fn truncate_last_column() {
+        let rows = [
+            Row::new(vec![
+                FormattedString::new("key"),
+                FormattedString::new("a long value"),
+            ]),
+            Row::new(vec![
+                FormattedString::new("k"),
+                FormattedString::new("ヒカルヒカル"),
+            ]),
+        ];
+        let result = format!("{:10}", Table::new(&rows));
+        let expected = "key a lon…\n\
+                        k   ヒカ…";
+        assert_eq!(&*result, expected);
+    }
+
+    #[test]
+    fn right_alignment() {
+        let rows = [
+            Row::new(vec![FormattedString::new("9"), FormattedString::new("x")]),
+            Row::new(vec![FormattedString::new("10"), FormattedString::new("y")]),
+            Row::new(vec![FormattedString::new("100"), FormattedString::new("z")]),
+        ];
+        let result = format!("{:100}", Table::new(&rows).align(0, Align::Right));
+        let expected = "  9 x\n\
+                        \x2010 y\n\
+                        100 z";
+        assert_eq!(&*result, expected);
+    }
 }
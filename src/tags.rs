@@ -6,7 +6,7 @@ pub struct Tags {
 impl Tags {
     pub fn from_song(song: &mpdrs::Song) -> Tags {
         Tags {
-            native_mpd: song.tags.clone(),
+            native_mpd: native_tags(song),
             raw_comments: vec![],
         }
     }
@@ -16,15 +16,15 @@ impl Tags {
         raw_comments: Vec<(String, String)>,
     ) -> Tags {
         Tags {
-            native_mpd: song.tags.clone(),
+            native_mpd: native_tags(song),
             raw_comments,
         }
     }
 
     pub fn get<'a>(&'a self, tag: &'a str) -> Vec<&'a str> {
-        let mut tags = tag_filter(&*self.native_mpd, tag).collect::<Vec<_>>();
+        let mut tags = tag_filter(&self.native_mpd, tag).collect::<Vec<_>>();
         if tags.is_empty() {
-            tags.extend(tag_filter(&*self.raw_comments, tag));
+            tags.extend(tag_filter(&self.raw_comments, tag));
         }
         tags.sort_unstable();
         tags.dedup();
@@ -48,6 +48,21 @@ impl Tags {
     }
 }
 
+// mpdrs moves some tags out of the tag list and into fields of the song, put
+// them back so that they can be looked up like any other tag.
+pub fn native_tags(song: &mpdrs::Song) -> Vec<(String, String)> {
+    let fields = [
+        ("Title", &song.title),
+        ("Artist", &song.artist),
+        ("Name", &song.name),
+    ];
+    fields
+        .iter()
+        .filter_map(|(k, v)| v.as_ref().map(|v| ((*k).to_string(), v.clone())))
+        .chain(song.tags.iter().cloned())
+        .collect()
+}
+
 fn tag_filter<'a>(vals: &'a [(String, String)], tag: &'a str) -> impl Iterator<Item = &'a str> {
     vals.iter().filter_map(move |(k, v)| {
         if k.to_uppercase() == tag.to_uppercase() {
@@ -57,3 +72,40 @@ fn tag_filter<'a>(vals: &'a [(String, String)], tag: &'a str) -> impl Iterator<I
         }
     })
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn song() -> mpdrs::Song {
+        mpdrs::Song {
+            file: "a.flac".into(),
+            title: Some("Title".into()),
+            artist: Some("Artist".into()),
+            tags: vec![("Album".into(), "Album".into())],
+            ..mpdrs::Song::default()
+        }
+    }
+
+    #[test]
+    fn song_fields() {
+        let tags = Tags::from_song(&song());
+        assert_eq!(tags.get("title"), vec!["Title"]);
+        assert_eq!(tags.get("Artist"), vec!["Artist"]);
+        assert_eq!(
+            tags.joined(&["album", "artist"], " - "),
+            Some("Album - Artist".into())
+        );
+    }
+
+    #[test]
+    fn song_fields_before_raw_comments() {
+        let raw_comments = vec![
+            ("ARTIST".into(), "Comment artist".into()),
+            ("LABEL".into(), "Label".into()),
+        ];
+        let tags = Tags::from_song_and_raw_comments(&song(), raw_comments);
+        assert_eq!(tags.get("artist"), vec!["Artist"]);
+        assert_eq!(tags.get("label"), vec!["Label"]);
+    }
+}
@@ -0,0 +1,52 @@
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
+
+// Display width of text in a terminal. Wide characters, such as CJK ideographs
+// and most emoji, take two columns, and combining characters take none.
+pub fn width(s: &str) -> usize {
+    UnicodeWidthStr::width(s)
+}
+
+// Truncate s to at most max columns, marking the truncation with an ellipsis.
+pub fn truncate(s: &str, max: usize) -> String {
+    if width(s) <= max {
+        return s.to_string();
+    }
+    let mut truncated = String::new();
+    let mut used = 0;
+    for c in s.chars() {
+        let w = c.width().unwrap_or(0);
+        if used + w + 1 > max {
+            break;
+        }
+        used += w;
+        truncated.push(c);
+    }
+    if max > 0 {
+        truncated.push('…');
+    }
+    truncated
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn widths() {
+        assert_eq!(width("abc"), 3);
+        assert_eq!(width("åäö"), 3);
+        assert_eq!(width("宇多田ヒカル"), 12);
+        assert_eq!(width("한국어"), 6);
+        assert_eq!(width("e\u{301}"), 1);
+        assert_eq!(width("🎷 jazz"), 7);
+    }
+
+    #[test]
+    fn truncation() {
+        assert_eq!(truncate("abcdef", 6), "abcdef");
+        assert_eq!(truncate("abcdef", 4), "abc…");
+        assert_eq!(truncate("宇多田ヒカル", 6), "宇多…");
+        assert_eq!(truncate("宇多田ヒカル", 5), "宇多…");
+        assert_eq!(truncate("abc", 0), "");
+    }
+}
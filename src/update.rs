@@ -0,0 +1,80 @@
+use crate::error::Error;
+use crate::json;
+use crate::raw;
+use crate::stats::print_key_values;
+use std::time::Duration;
+
+// Start a database update, or a rescan which also reads unmodified files, of
+// path or the whole database. With wait, block until MPD has finished and
+// print how the number of songs, albums and artists changed.
+pub fn update(
+    client: &mut mpdrs::Client,
+    raw: &mut raw::Connection,
+    path: Option<&str>,
+    rescan: bool,
+    wait: bool,
+    json: bool,
+) -> Result<(), Error> {
+    let before = if wait { Some(client.stats()?) } else { None };
+    let command = if rescan { "rescan" } else { "update" };
+    raw.command(command, path.as_slice())?;
+    let before = match before {
+        Some(before) => before,
+        None => return Ok(()),
+    };
+
+    // Queued updates run one after another, so wait until MPD is not
+    // updating at all rather than for a particular job.
+    while client.status()?.updating_db.is_some() {
+        while raw
+            .idle(&["database", "update"], Duration::from_secs(3600))?
+            .is_none()
+        {}
+    }
+    let after = client.stats()?;
+
+    let changes = [
+        ("Songs", "songs", before.songs, after.songs),
+        ("Albums", "albums", before.albums, after.albums),
+        ("Artists", "artists", before.artists, after.artists),
+    ];
+    if json {
+        let value = json::Value::object(
+            changes
+                .iter()
+                .map(|(_, key, before, after)| {
+                    (*key, (i64::from(*after) - i64::from(*before)).into())
+                })
+                .collect(),
+        );
+        println!("{}", value);
+        return Ok(());
+    }
+    let rows = changes
+        .iter()
+        .map(|(label, _, before, after)| (*label, change_format(*before, *after)))
+        .collect::<Vec<_>>();
+    print_key_values(&rows);
+    Ok(())
+}
+
+// Such as "2 added (7 in total)".
+fn change_format(before: u32, after: u32) -> String {
+    match after.cmp(&before) {
+        std::cmp::Ordering::Equal => format!("unchanged ({} in total)", after),
+        std::cmp::Ordering::Greater => format!("{} added ({} in total)", after - before, after),
+        std::cmp::Ordering::Less => format!("{} removed ({} in total)", before - after, after),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn changes() {
+        assert_eq!(change_format(5, 7), "2 added (7 in total)");
+        assert_eq!(change_format(5, 2), "3 removed (2 in total)");
+        assert_eq!(change_format(5, 5), "unchanged (5 in total)");
+    }
+}